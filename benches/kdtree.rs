@@ -0,0 +1,91 @@
+// Timings for the kd-tree on a million random points in the unit square.
+// Run with `cargo bench --bench kdtree`.
+use std::{hint::black_box, time::Instant};
+
+use algo::{
+    kdtrees::kdtree::{KdTree, SplitHV},
+    util::{point2d::Point2D, rect_hv::RectHV},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const POINTS: usize = 1_000_000;
+const QUERIES: usize = 100_000;
+
+fn time<T>(name: &str, n: usize, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = black_box(f());
+    let elapsed = start.elapsed();
+    println!(
+        "{name:<24} {elapsed:>12.2?} {:>10.0} ns/op",
+        elapsed.as_nanos() as f64 / n as f64
+    );
+    result
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let points: Vec<Point2D> = (0..POINTS)
+        .map(|_| Point2D::new(rng.gen(), rng.gen()))
+        .collect();
+    let queries: Vec<Point2D> = (0..QUERIES)
+        .map(|_| Point2D::new(rng.gen(), rng.gen()))
+        .collect();
+
+    time("insert", POINTS, || {
+        let mut tree = KdTree::new(SplitHV::V);
+        for p in points.iter() {
+            tree.insert(p.clone());
+        }
+        tree
+    });
+    let tree = time("from_points", POINTS, || {
+        KdTree::from_points(points.clone())
+    });
+    let mut file = vec![];
+    time("save", POINTS, || tree.save(&mut file).unwrap());
+    time("load", POINTS, || KdTree::load(file.as_slice()).unwrap());
+
+    time("contains", QUERIES, || {
+        points[..QUERIES]
+            .iter()
+            .filter(|p| tree.contains((*p).clone()))
+            .count()
+    });
+    time("nearest_k(1)", QUERIES, || {
+        queries
+            .iter()
+            .map(|q| tree.nearest_k(q.clone(), 1).len())
+            .sum::<usize>()
+    });
+    time("nearest", QUERIES, || {
+        queries
+            .iter()
+            .filter_map(|q| tree.nearest(q.clone()))
+            .count()
+    });
+    time("nearest_approx(0.5)", QUERIES, || {
+        queries
+            .iter()
+            .filter_map(|q| tree.nearest_approx(q.clone(), 0.5))
+            .count()
+    });
+    time("nearest_many", QUERIES, || {
+        tree.nearest_many(&queries).len()
+    });
+    time("nearest_k(10)", QUERIES, || {
+        queries
+            .iter()
+            .map(|q| tree.nearest_k(q.clone(), 10).len())
+            .sum::<usize>()
+    });
+    time("range (0.01 x 0.01)", QUERIES, || {
+        queries
+            .iter()
+            .map(|q| {
+                let rect = RectHV::new(q.x(), q.y(), q.x() + 0.01, q.y() + 0.01);
+                tree.range_iter(&rect).count()
+            })
+            .sum::<usize>()
+    });
+    time("size", 10, || (0..10).map(|_| tree.size()).sum::<usize>());
+}
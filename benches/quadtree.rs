@@ -0,0 +1,114 @@
+// PRQuadTree against KdTree, on uniform points and on tight clusters, which is
+// where the two shapes differ most. Run with `cargo bench --bench quadtree`.
+use std::{hint::black_box, time::Instant};
+
+use algo::{
+    kdtrees::{
+        kdtree::{KdTree, SplitHV},
+        point_index::PointIndex,
+    },
+    quadtree::pr_quadtree::PRQuadTree,
+    util::{point2d::Point2D, rect_hv::RectHV},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const POINTS: usize = 1_000_000;
+const QUERIES: usize = 100_000;
+
+fn time<T>(name: &str, n: usize, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = black_box(f());
+    let elapsed = start.elapsed();
+    println!(
+        "{name:<32} {elapsed:>12.2?} {:>10.0} ns/op",
+        elapsed.as_nanos() as f64 / n as f64
+    );
+    result
+}
+
+// 50 gaussian clusters with standard deviations from 0.001 to 0.02
+fn clustered(n: usize, rng: &mut StdRng) -> Vec<Point2D> {
+    let centers: Vec<(f64, f64, f64)> = (0..50)
+        .map(|_| (rng.gen(), rng.gen(), rng.gen_range(0.001..0.02)))
+        .collect();
+    // Box-Muller
+    let normal = |rng: &mut StdRng| {
+        let (u, v): (f64, f64) = (1.0 - rng.gen::<f64>(), rng.gen());
+        (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+    };
+    (0..n)
+        .map(|_| {
+            let (x, y, sd) = centers[rng.gen_range(0..centers.len())];
+            Point2D::new(x + sd * normal(rng), y + sd * normal(rng))
+        })
+        .collect()
+}
+
+fn bench<I: PointIndex>(name: &str, mut index: I, points: &[Point2D], queries: &[Point2D]) {
+    time(&format!("{name} insert"), points.len(), || {
+        for p in points.iter() {
+            index.insert(p.clone());
+        }
+    });
+    time(&format!("{name} contains"), queries.len(), || {
+        points[..queries.len()]
+            .iter()
+            .filter(|p| index.contains((*p).clone()))
+            .count()
+    });
+    time(&format!("{name} nearest"), queries.len(), || {
+        queries
+            .iter()
+            .filter_map(|q| index.nearest(q.clone()))
+            .count()
+    });
+    time(&format!("{name} nearest_k(10)"), queries.len(), || {
+        queries
+            .iter()
+            .map(|q| index.nearest_k(q.clone(), 10).len())
+            .sum::<usize>()
+    });
+    time(
+        &format!("{name} range (0.01 x 0.01)"),
+        queries.len(),
+        || {
+            queries
+                .iter()
+                .map(|q| {
+                    let rect = RectHV::new(q.x(), q.y(), q.x() + 0.01, q.y() + 0.01);
+                    index.range(&rect).len()
+                })
+                .sum::<usize>()
+        },
+    );
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let uniform: Vec<Point2D> = (0..POINTS)
+        .map(|_| Point2D::new(rng.gen(), rng.gen()))
+        .collect();
+    let clustered = clustered(POINTS, &mut rng);
+    // queries follow the data, as they would in practice
+    let uniform_queries: Vec<Point2D> = (0..QUERIES)
+        .map(|_| Point2D::new(rng.gen(), rng.gen()))
+        .collect();
+    let clustered_queries: Vec<Point2D> = (0..QUERIES)
+        .map(|_| clustered[rng.gen_range(0..POINTS)].clone())
+        .collect();
+
+    for (data, points, queries) in [
+        ("uniform", &uniform, &uniform_queries),
+        ("clustered", &clustered, &clustered_queries),
+    ] {
+        println!("{data}:");
+        bench("kdtree", KdTree::new(SplitHV::V), points, queries);
+        bench("quadtree", PRQuadTree::new(), points, queries);
+        bench(
+            "quadtree(16)",
+            PRQuadTree::new().with_node_capacity(16),
+            points,
+            queries,
+        );
+    }
+}
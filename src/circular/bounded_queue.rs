@@ -0,0 +1,517 @@
+// A blocking, bounded, multi-producer/multi-consumer queue: a CircularBuffer
+// behind a Mutex, with one Condvar for "there is room" and one for "there is an item".
+//
+// Senders block while the queue is full, which gives callers backpressure.
+// Once the queue is closed no new items are accepted, but receivers keep
+// draining whatever is left before they see `Closed`.
+//
+// A BoundedQueue shared through an `Arc` is only closed by calling `close()`.
+// The Sender and Receiver handles from `channel` close it on their own: when the
+// last Sender is dropped receivers get `Closed` once the queue is drained, and
+// when the last Receiver is dropped senders get their item back with `Closed`.
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use super::CircularBuffer;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("sending on a closed queue")]
+pub struct SendError<T>(pub T);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    #[error("queue is full")]
+    Full(T),
+    #[error("sending on a closed queue")]
+    Closed(T),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SendTimeoutError<T> {
+    #[error("timed out waiting for room in the queue")]
+    Timeout(T),
+    #[error("sending on a closed queue")]
+    Closed(T),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("receiving on a closed and empty queue")]
+pub struct RecvError;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    #[error("queue is empty")]
+    Empty,
+    #[error("receiving on a closed and empty queue")]
+    Closed,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    #[error("timed out waiting for an item")]
+    Timeout,
+    #[error("receiving on a closed and empty queue")]
+    Closed,
+}
+
+struct State<T> {
+    buf: CircularBuffer<T>,
+    closed: bool,
+    // live handles; both stay 0 for a queue made with `new`
+    senders: usize,
+    receivers: usize,
+}
+
+/// Share it between threads with an `Arc`.
+pub struct BoundedQueue<T> {
+    state: Mutex<State<T>>,
+    not_full: Condvar,
+    not_empty: Condvar,
+}
+
+impl<T> BoundedQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                buf: CircularBuffer::new(capacity),
+                closed: false,
+                senders: 0,
+                receivers: 0,
+            }),
+            not_full: Condvar::new(),
+            not_empty: Condvar::new(),
+        }
+    }
+
+    // a panic while holding the lock can't leave the buffer half updated, so ignore poisoning
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.lock().buf.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().buf.is_empty()
+    }
+
+    // stop accepting items and wake everyone up; items already queued can still be received
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.not_full.notify_all();
+        self.not_empty.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    fn put(&self, mut state: MutexGuard<'_, State<T>>, item: T) {
+        if state.buf.enqueue(item).is_err() {
+            unreachable!("put is only called when there is room");
+        }
+        drop(state);
+        self.not_empty.notify_one();
+    }
+
+    fn take(&self, mut state: MutexGuard<'_, State<T>>) -> Option<T> {
+        let item = state.buf.dequeue();
+        drop(state);
+        self.not_full.notify_one();
+        item
+    }
+
+    // add the item, waiting for room if the queue is full
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let mut state = self.lock();
+        while state.buf.is_full() && !state.closed {
+            state = self.not_full.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.closed {
+            return Err(SendError(item));
+        }
+        self.put(state, item);
+        Ok(())
+    }
+
+    // add the item only if there is room right now
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        let state = self.lock();
+        if state.closed {
+            Err(TrySendError::Closed(item))
+        } else if state.buf.is_full() {
+            Err(TrySendError::Full(item))
+        } else {
+            self.put(state, item);
+            Ok(())
+        }
+    }
+
+    // add the item, waiting at most `timeout` for room
+    pub fn send_timeout(&self, item: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        // a timeout too long to have a deadline is no timeout at all
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self
+                .send(item)
+                .map_err(|SendError(item)| SendTimeoutError::Closed(item));
+        };
+        let mut state = self.lock();
+        while state.buf.is_full() && !state.closed {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(SendTimeoutError::Timeout(item));
+            }
+            state = self
+                .not_full
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        if state.closed {
+            return Err(SendTimeoutError::Closed(item));
+        }
+        self.put(state, item);
+        Ok(())
+    }
+
+    // remove and return the oldest item, waiting for one if the queue is empty
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.lock();
+        while state.buf.is_empty() {
+            if state.closed {
+                return Err(RecvError);
+            }
+            state = self
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        self.take(state).ok_or(RecvError)
+    }
+
+    // remove and return the oldest item only if one is available right now
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let state = self.lock();
+        if !state.buf.is_empty() {
+            self.take(state).ok_or(TryRecvError::Empty)
+        } else if state.closed {
+            Err(TryRecvError::Closed)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    // remove and return the oldest item, waiting at most `timeout` for one
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self.recv().map_err(|RecvError| RecvTimeoutError::Closed);
+        };
+        let mut state = self.lock();
+        while state.buf.is_empty() {
+            if state.closed {
+                return Err(RecvTimeoutError::Closed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .not_empty
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        self.take(state).ok_or(RecvTimeoutError::Timeout)
+    }
+}
+
+/// The sending half of a queue made by `channel`; clone it for more producers.
+pub struct Sender<T> {
+    queue: Arc<BoundedQueue<T>>,
+}
+
+/// The receiving half of a queue made by `channel`; clone it for more consumers.
+pub struct Receiver<T> {
+    queue: Arc<BoundedQueue<T>>,
+}
+
+/// Create a queue holding up to `capacity` items, closed when either side's
+/// handles are all dropped.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let queue = BoundedQueue::new(capacity);
+    {
+        let mut state = queue.lock();
+        state.senders = 1;
+        state.receivers = 1;
+    }
+    let queue = Arc::new(queue);
+    (
+        Sender {
+            queue: queue.clone(),
+        },
+        Receiver { queue },
+    )
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        self.queue.send(item)
+    }
+
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        self.queue.try_send(item)
+    }
+
+    pub fn send_timeout(&self, item: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        self.queue.send_timeout(item, timeout)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // closed by hand or because every Receiver is gone
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+
+    pub fn close(&self) {
+        self.queue.close()
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.queue.recv()
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.queue.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.queue.recv_timeout(timeout)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // closed by hand or because every Sender is gone
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+
+    pub fn close(&self) {
+        self.queue.close()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.queue.lock().senders += 1;
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.queue.lock().receivers += 1;
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.queue.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.queue.close();
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.queue.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            drop(state);
+            self.queue.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test() {
+        let q = BoundedQueue::new(2);
+        assert_eq!(q.try_recv(), Err(TryRecvError::Empty));
+        q.send(1).unwrap();
+        q.try_send(2).unwrap();
+        assert_eq!(q.try_send(3), Err(TrySendError::Full(3)));
+        let timeout = Duration::from_millis(10);
+        assert_eq!(
+            q.send_timeout(3, timeout),
+            Err(SendTimeoutError::Timeout(3))
+        );
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.recv(), Ok(1));
+        assert_eq!(q.recv_timeout(timeout), Ok(2));
+        assert_eq!(q.recv_timeout(timeout), Err(RecvTimeoutError::Timeout));
+    }
+
+    #[test]
+    fn test_close() {
+        let q = BoundedQueue::new(4);
+        q.send("a").unwrap();
+        q.send("b").unwrap();
+        q.close();
+        assert_eq!(q.send("c"), Err(SendError("c")));
+        assert_eq!(q.try_send("c"), Err(TrySendError::Closed("c")));
+        // what was queued before the close is still delivered
+        assert_eq!(q.recv(), Ok("a"));
+        assert_eq!(q.try_recv(), Ok("b"));
+        assert_eq!(q.recv(), Err(RecvError));
+        assert_eq!(q.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[test]
+    fn test_close_wakes_blocked() {
+        let q = Arc::new(BoundedQueue::<u32>::new(1));
+        let r = q.clone();
+        let receiver = thread::spawn(move || r.recv());
+        thread::sleep(Duration::from_millis(20));
+        q.close();
+        assert_eq!(receiver.join().unwrap(), Err(RecvError));
+    }
+
+    #[test]
+    fn test_last_sender_dropped() {
+        let (s, r) = channel::<u32>(2);
+        let s2 = s.clone();
+        s.send(1).unwrap();
+        let receiver = thread::spawn(move || (r.recv(), r.recv()));
+        drop(s);
+        // one sender is left, so the receiver is still waiting for its second item
+        thread::sleep(Duration::from_millis(20));
+        assert!(!receiver.is_finished());
+        drop(s2);
+        assert_eq!(receiver.join().unwrap(), (Ok(1), Err(RecvError)));
+    }
+
+    #[test]
+    fn test_last_receiver_dropped() {
+        let (s, r) = channel(1);
+        s.send(1).unwrap();
+        let r2 = r.clone();
+        let sender = thread::spawn(move || s.send(2));
+        thread::sleep(Duration::from_millis(20));
+        drop(r);
+        drop(r2);
+        assert_eq!(sender.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_endless_timeout() {
+        // Duration::MAX can't be added to an Instant, so it waits like send and recv
+        let q = Arc::new(BoundedQueue::new(1));
+        q.send(1).unwrap();
+        let r = q.clone();
+        let receiver = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            (r.recv(), r.recv_timeout(Duration::MAX))
+        });
+        assert_eq!(q.send_timeout(2, Duration::MAX), Ok(()));
+        assert_eq!(receiver.join().unwrap(), (Ok(1), Ok(2)));
+        q.close();
+        assert_eq!(
+            q.send_timeout(3, Duration::MAX),
+            Err(SendTimeoutError::Closed(3))
+        );
+        assert_eq!(q.recv_timeout(Duration::MAX), Err(RecvTimeoutError::Closed));
+    }
+
+    #[test]
+    fn test_threads() {
+        const PRODUCERS: u64 = 4;
+        const N: u64 = 10_000;
+        let q = Arc::new(BoundedQueue::new(8));
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    for i in 0..N {
+                        q.send((p, i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let q = q.clone();
+                thread::spawn(move || {
+                    let mut got = vec![];
+                    while let Ok(item) = q.recv() {
+                        got.push(item);
+                    }
+                    got
+                })
+            })
+            .collect();
+        for p in producers {
+            p.join().unwrap();
+        }
+        q.close();
+
+        let mut all = vec![];
+        for c in consumers {
+            let got = c.join().unwrap();
+            // each consumer sees every producer's items in the order they were sent
+            for p in 0..PRODUCERS {
+                let seq: Vec<u64> = got.iter().filter(|x| x.0 == p).map(|x| x.1).collect();
+                assert!(seq.windows(2).all(|w| w[0] < w[1]));
+            }
+            all.extend(got);
+        }
+        all.sort();
+        assert_eq!(all.len() as u64, PRODUCERS * N);
+        all.dedup();
+        assert_eq!(all.len() as u64, PRODUCERS * N);
+    }
+}
@@ -0,0 +1,291 @@
+// Detectors for the two stream problems described at the top of `circular`:
+//   EventWindow: alert when the most recent k events all fall within a window of time.
+//   RateMonitor: alert when the average of the last N values drops below a threshold.
+// Both keep their history in a CircularBuffer so memory is fixed at construction.
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use super::CircularBuffer;
+
+/// A source of timestamps. Ticks are plain `u64`s; the unit is whatever the
+/// clock (or the caller of `record_at`) uses consistently.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> u64 {
+        (**self).now()
+    }
+}
+
+/// Milliseconds elapsed since the clock was created.
+pub struct InstantClock {
+    origin: Instant,
+}
+
+impl InstantClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+
+    // convert an Instant to ticks of this clock (instants before the origin map to 0)
+    pub fn ticks(&self, t: Instant) -> u64 {
+        t.saturating_duration_since(self.origin).as_millis() as u64
+    }
+}
+
+impl Default for InstantClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for InstantClock {
+    fn now(&self) -> u64 {
+        self.ticks(Instant::now())
+    }
+}
+
+/// A clock that only moves when told to. Use it in tests or when replaying logs.
+#[derive(Debug, Default)]
+pub struct ManualClock(Cell<u64>);
+
+impl ManualClock {
+    pub fn new(start: u64) -> Self {
+        Self(Cell::new(start))
+    }
+
+    pub fn set(&self, t: u64) {
+        self.0.set(t);
+    }
+
+    pub fn advance(&self, ticks: u64) {
+        self.0.set(self.0.get() + ticks);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alert {
+    // `count` events arrived between ticks `first` and `last`
+    Burst { count: usize, first: u64, last: u64 },
+    // the average over the last `samples` values fell below the threshold
+    RateBelow { samples: usize, average: f64 },
+}
+
+type Callback = Box<dyn FnMut(&Alert)>;
+
+/// Raises an alert whenever the most recent `k` events land strictly inside
+/// `window` ticks of each other, e.g. "the last 3 failures were within 2 minutes".
+pub struct EventWindow<C = InstantClock> {
+    times: CircularBuffer<u64>,
+    window: u64,
+    clock: C,
+    on_alert: Option<Callback>,
+}
+
+impl EventWindow<InstantClock> {
+    // detector on the system clock, ticking in milliseconds
+    pub fn new(k: usize, window: Duration) -> Self {
+        Self::with_clock(k, window.as_millis() as u64, InstantClock::new())
+    }
+
+    // record an event that happened at `t`
+    pub fn record_instant(&mut self, t: Instant) -> Option<Alert> {
+        let ticks = self.clock.ticks(t);
+        self.record_at(ticks)
+    }
+}
+
+impl<C: Clock> EventWindow<C> {
+    pub fn with_clock(k: usize, window: u64, clock: C) -> Self {
+        assert!(k > 0, "an event window needs room for at least one event");
+        Self {
+            times: CircularBuffer::new(k),
+            window,
+            clock,
+            on_alert: None,
+        }
+    }
+
+    // call `f` with every alert, in addition to returning it
+    pub fn on_alert(&mut self, f: impl FnMut(&Alert) + 'static) {
+        self.on_alert = Some(Box::new(f));
+    }
+
+    // record an event happening now, according to the clock
+    pub fn record(&mut self) -> Option<Alert> {
+        let t = self.clock.now();
+        self.record_at(t)
+    }
+
+    // record an event with a caller supplied timestamp; timestamps should not go backwards
+    pub fn record_at(&mut self, t: u64) -> Option<Alert> {
+        if self.times.is_full() {
+            self.times.dequeue();
+        }
+        self.times.enqueue(t).expect("an old time was just dropped");
+        if !self.times.is_full() {
+            return None;
+        }
+        let first = (*self.times.peek_oldest())?;
+        if t.saturating_sub(first) < self.window {
+            let alert = Alert::Burst {
+                count: self.times.len(),
+                first,
+                last: t,
+            };
+            if let Some(f) = self.on_alert.as_mut() {
+                f(&alert);
+            }
+            Some(alert)
+        } else {
+            None
+        }
+    }
+}
+
+/// Raises an alert on every value received while the average of the last
+/// `samples` values is below `threshold`. Nothing is raised until `samples`
+/// values have been seen.
+pub struct RateMonitor {
+    values: CircularBuffer<f64>,
+    sum: f64,
+    threshold: f64,
+    on_alert: Option<Callback>,
+}
+
+impl RateMonitor {
+    pub fn new(samples: usize, threshold: f64) -> Self {
+        assert!(samples > 0, "a rate monitor needs at least one sample to average");
+        Self {
+            values: CircularBuffer::new(samples),
+            sum: 0.0,
+            threshold,
+            on_alert: None,
+        }
+    }
+
+    // call `f` with every alert, in addition to returning it
+    pub fn on_alert(&mut self, f: impl FnMut(&Alert) + 'static) {
+        self.on_alert = Some(Box::new(f));
+    }
+
+    // average of the last `samples` values, or None if not enough have been seen yet
+    pub fn average(&self) -> Option<f64> {
+        if self.values.is_full() {
+            Some(self.sum / self.values.len() as f64)
+        } else {
+            None
+        }
+    }
+
+    pub fn record(&mut self, value: f64) -> Option<Alert> {
+        if self.values.is_full() {
+            if let Some(old) = self.values.dequeue() {
+                self.sum -= old;
+            }
+        }
+        self.values.enqueue(value).expect("an old value was just dropped");
+        self.sum += value;
+
+        let average = self.average()?;
+        if average < self.threshold {
+            let alert = Alert::RateBelow {
+                samples: self.values.len(),
+                average,
+            };
+            if let Some(f) = self.on_alert.as_mut() {
+                f(&alert);
+            }
+            Some(alert)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[test]
+    fn test_burst() {
+        // same stream as circular::test_time: 3 failures within 2 minutes
+        let mut w = EventWindow::with_clock(3, 120, ManualClock::new(0));
+        let d = [
+            0, 60, 120, 240, 300, 360, 420, 480, 540, 600, 600, 660, 720, 780,
+        ];
+        let alerts: Vec<u64> = d
+            .iter()
+            .filter_map(|t| match w.record_at(*t) {
+                Some(Alert::Burst { last, .. }) => Some(last),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(alerts, vec![600, 660]);
+    }
+
+    #[test]
+    fn test_clock_and_callback() {
+        let clock = ManualClock::new(1000);
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut w = EventWindow::with_clock(2, 10, &clock);
+        let s = seen.clone();
+        w.on_alert(move |a| s.borrow_mut().push(a.clone()));
+
+        assert_eq!(w.record(), None);
+        clock.advance(20);
+        assert_eq!(w.record(), None);
+        clock.advance(5);
+        let alert = Some(Alert::Burst {
+            count: 2,
+            first: 1020,
+            last: 1025,
+        });
+        assert_eq!(w.record(), alert);
+        assert_eq!(seen.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_instant() {
+        let mut w = EventWindow::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        assert_eq!(w.record_instant(now), None);
+        assert!(w.record_instant(now + Duration::from_secs(1)).is_some());
+        assert!(w.record_instant(now + Duration::from_secs(120)).is_none());
+    }
+
+    #[test]
+    fn test_rate() {
+        // same stream as circular::test_avg
+        let mut m = RateMonitor::new(10, 5.0);
+        for _ in 0..9 {
+            assert_eq!(m.record(0.0), None);
+        }
+        assert_eq!(m.average(), None);
+        assert!(m.record(0.0).is_some());
+
+        let mut m = RateMonitor::new(10, 5.0);
+        for _ in 0..10 {
+            m.record(10.0);
+        }
+        let d = [2, 10, 10, 15, 15, 2, 0, 2, 2, 2, 0, 0, 0, 0, 0];
+        let below: Vec<bool> = d.iter().map(|v| m.record(*v as f64).is_some()).collect();
+        let first = below.iter().position(|b| *b);
+        assert_eq!(first, Some(11));
+        assert!(below[11..].iter().all(|b| *b));
+    }
+}
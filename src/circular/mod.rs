@@ -8,15 +8,21 @@
 //              the average is over at least 10 elements.
 //  problem 2: put a sequence of values into the queue with timestamps. call them failures.
 //             print a message at any point if the most recent 3 values are within 2 minutes.
+//             (both problems are packaged up in `event_window`)
+
+pub mod bounded_queue;
+pub mod event_window;
+pub mod spsc;
+
 #[derive(Debug)]
-struct CircularBuffer<T> {
+pub struct CircularBuffer<T> {
     buf: Box<[Option<T>]>,
     en_pos: usize,
     de_pos: usize,
 }
 
 impl<T> CircularBuffer<T> {
-    fn new(size: usize) -> Self {
+    pub fn new(size: usize) -> Self {
         assert!(size > 0);
         // size+1 to maintain a gap between en and de
         // Option is not Clone so you can't do vec![None; size+1]
@@ -28,17 +34,42 @@ impl<T> CircularBuffer<T> {
             de_pos: 0,
         }
     }
-    fn enqueue(&mut self, item: T) {
+    // number of items the buffer can hold
+    pub fn capacity(&self) -> usize {
+        self.buf.len() - 1
+    }
+
+    // number of items currently in the buffer
+    pub fn len(&self) -> usize {
+        if self.en_pos >= self.de_pos {
+            self.en_pos - self.de_pos
+        } else {
+            self.buf.len() - self.de_pos + self.en_pos
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.de_pos == self.en_pos
+    }
+
+    // is there no room for another enqueue?
+    pub fn is_full(&self) -> bool {
+        (self.en_pos + 1) % self.buf.len() == self.de_pos
+    }
+
+    // add the item as the newest; a full buffer hands it back
+    pub fn enqueue(&mut self, item: T) -> Result<(), T> {
         // if the next pos is de then enqueue should fail
         let next_pos = (self.en_pos + 1) % self.buf.len();
         if next_pos == self.de_pos {
-            todo!();
+            Err(item)
         } else {
             self.buf[self.en_pos] = Some(item);
             self.en_pos = next_pos;
+            Ok(())
         }
     }
-    fn dequeue(&mut self) -> Option<T> {
+    pub fn dequeue(&mut self) -> Option<T> {
         if self.de_pos == self.en_pos {
             None
         } else {
@@ -47,10 +78,10 @@ impl<T> CircularBuffer<T> {
             item
         }
     }
-    fn peek_oldest(&self) -> &Option<T> {
+    pub fn peek_oldest(&self) -> &Option<T> {
         &self.buf[self.de_pos]
     }
-    fn peek_newest(&self) -> &Option<T> {
+    pub fn peek_newest(&self) -> &Option<T> {
         let new_pos = if self.en_pos == 0 {
             self.buf.len() - 1
         } else {
@@ -66,7 +97,7 @@ fn test_avg() {
     let mut c = CircularBuffer::new(10);
     // load with ten items
     for _ in 0..10 {
-        c.enqueue(10);
+        c.enqueue(10).unwrap();
     }
     let mut sum = 100;
     let mut avg = sum / 10;
@@ -77,7 +108,7 @@ fn test_avg() {
         if let Some(item) = c.dequeue() {
             sum -= item;
         }
-        c.enqueue(i);
+        c.enqueue(i).unwrap();
         sum += i;
         avg = sum / 10;
         println!("sum:{sum}, avg:{avg}, below:{}",avg<5);
@@ -89,7 +120,7 @@ fn test_time() {
     let mut c = CircularBuffer::new(3);
     // load with items
     for i in 0..3 {
-        c.enqueue(i*60);
+        c.enqueue(i*60).unwrap();
     }
     let mut dif = 2;
 
@@ -97,7 +128,7 @@ fn test_time() {
     let d = [4*60,5*60,6*60,7*60,8*60,9*60,10*60,10*60,11*60,12*60,13*60];
     for i in d {
         c.dequeue();
-        c.enqueue(i);
+        c.enqueue(i).unwrap();
 
         if let Some(item) = c.peek_oldest() {
             dif = i-item;            
@@ -106,18 +137,36 @@ fn test_time() {
     }
 }
 
+#[test]
+fn test_len() {
+    let mut c = CircularBuffer::new(3);
+    assert!(c.is_empty());
+    assert_eq!(c.capacity(), 3);
+    for i in 0..3 {
+        c.enqueue(i).unwrap();
+    }
+    assert!(c.is_full());
+    assert_eq!(c.enqueue(9), Err(9));
+    assert_eq!(c.len(), 3);
+    c.dequeue();
+    c.dequeue();
+    c.enqueue(3).unwrap();
+    assert_eq!(c.len(), 2);
+    assert!(!c.is_full());
+}
+
 #[test]
 fn test() {
     let mut c = CircularBuffer::new(3);
-    c.enqueue(1);
-    c.enqueue(2);
-    c.enqueue(3);
+    c.enqueue(1).unwrap();
+    c.enqueue(2).unwrap();
+    c.enqueue(3).unwrap();
     println!("{:?}", c);
 
     let d = c.dequeue();
     println!("{:?}", d);
     println!("{:?}", c);
-    c.enqueue(4);
+    c.enqueue(4).unwrap();
     let d = c.dequeue();
     println!("{:?}", d);
     println!("{:?}", c);
@@ -125,11 +174,11 @@ fn test() {
     let d = c.peek_newest();
     println!("New: {:?}", d);
 
-    c.enqueue(5);
+    c.enqueue(5).unwrap();
     let d = c.dequeue();
     println!("{:?}", d);
     println!("{:?}", c);
-    c.enqueue(6);
+    c.enqueue(6).unwrap();
     println!("{:?}", c);
 
     let d = c.peek_newest();
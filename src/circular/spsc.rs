@@ -0,0 +1,251 @@
+// Lock-free single-producer/single-consumer ring buffer.
+//
+// Same idea as CircularBuffer but shared between two threads. The producer only
+// ever writes `tail` and the consumer only ever writes `head`, so each side can
+// publish its progress with a single atomic store. `head` and `tail` count up
+// forever (wrapping) and are masked down to a slot index; the buffer is full
+// when they are `capacity` apart, so no slot has to be left empty as a gap.
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+// keep head and tail on separate cache lines so the two threads don't fight over one
+#[repr(align(64))]
+struct CachePadded(AtomicUsize);
+
+struct Ring<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    mask: usize,
+    head: CachePadded,
+    tail: CachePadded,
+}
+
+// Slots are only touched by the side that currently owns them (see push/pop),
+// and ownership is handed over through the acquire/release pairs on head and tail.
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        let head = *self.head.0.get_mut();
+        let tail = *self.tail.0.get_mut();
+        let mut i = head;
+        while i != tail {
+            // slots between head and tail were written and never read
+            unsafe { self.slots[i & self.mask].get_mut().assume_init_drop() };
+            i = i.wrapping_add(1);
+        }
+    }
+}
+
+/// The sending half of the ring. Only one exists, so it can be moved to
+/// another thread but not shared.
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+    // local copy of tail; only this side writes it
+    tail: usize,
+    // last head seen, refreshed only when the ring looks full
+    head_cache: usize,
+}
+
+/// The receiving half of the ring.
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+    head: usize,
+    tail_cache: usize,
+}
+
+/// Create a ring holding `capacity` items, rounded up to the next power of two.
+pub fn channel<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0);
+    let capacity = capacity.next_power_of_two();
+    let slots = (0..capacity)
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let ring = Arc::new(Ring {
+        slots,
+        mask: capacity - 1,
+        head: CachePadded(AtomicUsize::new(0)),
+        tail: CachePadded(AtomicUsize::new(0)),
+    });
+    (
+        Producer {
+            ring: ring.clone(),
+            tail: 0,
+            head_cache: 0,
+        },
+        Consumer {
+            ring,
+            head: 0,
+            tail_cache: 0,
+        },
+    )
+}
+
+impl<T> Producer<T> {
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+
+    // add the item, or hand it back if the ring is full
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        if self.tail.wrapping_sub(self.head_cache) == self.capacity() {
+            self.head_cache = self.ring.head.0.load(Ordering::Acquire);
+            if self.tail.wrapping_sub(self.head_cache) == self.capacity() {
+                return Err(item);
+            }
+        }
+        let slot = &self.ring.slots[self.tail & self.ring.mask];
+        // the consumer has released this slot (head moved past it) and won't read
+        // it again until we publish the new tail below
+        unsafe { (*slot.get()).write(item) };
+        self.tail = self.tail.wrapping_add(1);
+        self.ring.tail.0.store(self.tail, Ordering::Release);
+        Ok(())
+    }
+
+    // number of items waiting; may be stale by the time it is read
+    pub fn len(&self) -> usize {
+        self.tail
+            .wrapping_sub(self.ring.head.0.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // has the consumer been dropped?
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.ring) == 1
+    }
+}
+
+impl<T> Consumer<T> {
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+
+    // remove and return the oldest item, or None if the ring is empty
+    pub fn pop(&mut self) -> Option<T> {
+        if self.head == self.tail_cache {
+            self.tail_cache = self.ring.tail.0.load(Ordering::Acquire);
+            if self.head == self.tail_cache {
+                return None;
+            }
+        }
+        let slot = &self.ring.slots[self.head & self.ring.mask];
+        // the producer published this slot with the tail store we acquired above
+        let item = unsafe { (*slot.get()).assume_init_read() };
+        self.head = self.head.wrapping_add(1);
+        self.ring.head.0.store(self.head, Ordering::Release);
+        Some(item)
+    }
+
+    // the oldest item without removing it
+    pub fn peek(&mut self) -> Option<&T> {
+        if self.head == self.tail_cache {
+            self.tail_cache = self.ring.tail.0.load(Ordering::Acquire);
+            if self.head == self.tail_cache {
+                return None;
+            }
+        }
+        let slot = &self.ring.slots[self.head & self.ring.mask];
+        Some(unsafe { (*slot.get()).assume_init_ref() })
+    }
+
+    // number of items waiting; may be stale by the time it is read
+    pub fn len(&self) -> usize {
+        self.ring
+            .tail
+            .0
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // has the producer been dropped? Items already pushed can still be popped.
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.ring) == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test() {
+        let (mut p, mut c) = channel(3);
+        assert_eq!(p.capacity(), 4);
+        for i in 0..4 {
+            assert_eq!(p.push(i), Ok(()));
+        }
+        assert_eq!(p.push(4), Err(4));
+        assert_eq!(c.len(), 4);
+        assert_eq!(c.peek(), Some(&0));
+        assert_eq!(c.pop(), Some(0));
+        assert_eq!(p.push(4), Ok(()));
+        for i in 1..5 {
+            assert_eq!(c.pop(), Some(i));
+        }
+        assert_eq!(c.pop(), None);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn test_drop() {
+        // items still in the ring are dropped with it
+        let item = Arc::new(());
+        {
+            let (mut p, mut c) = channel::<Arc<()>>(4);
+            p.push(item.clone()).unwrap();
+            p.push(item.clone()).unwrap();
+            p.push(item.clone()).unwrap();
+            c.pop();
+            assert_eq!(Arc::strong_count(&item), 3);
+            drop(p);
+            assert!(c.is_abandoned());
+        }
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn test_threads() {
+        const N: u64 = 1_000_000;
+        let (mut p, mut c) = channel(64);
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                let mut item = i;
+                while let Err(back) = p.push(item) {
+                    item = back;
+                    thread::yield_now();
+                }
+            }
+        });
+        let consumer = thread::spawn(move || {
+            let mut expected = 0;
+            while expected < N {
+                match c.pop() {
+                    Some(i) => {
+                        assert_eq!(i, expected);
+                        expected += 1;
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+            assert_eq!(c.pop(), None);
+            expected
+        });
+        producer.join().unwrap();
+        assert_eq!(consumer.join().unwrap(), N);
+    }
+}
@@ -8,4 +8,10 @@ pub enum AlgoError {
     NoBlockedSites,
     #[error("drawing error")]
     DrawError,
+    #[error("i/o error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("corrupt data: {0}")]
+    CorruptData(&'static str),
+    #[error("unsupported format version {0}")]
+    UnsupportedVersion(u32),
 }
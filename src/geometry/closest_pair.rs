@@ -0,0 +1,153 @@
+use crate::util::point2d::Point2D;
+
+/* The closest pair of points, by divide and conquer in O(n log n) (as in Sedgewick
+and Wayne's ClosestPair). Sort the points by x and split them at the median: the
+closest pair is in the left half, in the right half, or straddles the split. For the
+last case only points within delta of the split line matter, delta being the best
+distance of the two halves. Taken in order of y, each of those needs comparing only
+with the few before it that are less than delta below it. The order by y comes from
+merging the halves on the way back up, as in mergesort, instead of sorting again.
+Distances are compared squared, so no square roots are taken. */
+pub struct ClosestPair {
+    pair: Option<(Point2D, Point2D)>,
+    distance_squared: f64,
+}
+
+fn by_x(a: &&Point2D, b: &&Point2D) -> std::cmp::Ordering {
+    a.x().total_cmp(&b.x()).then(a.y().total_cmp(&b.y()))
+}
+
+impl ClosestPair {
+    // the closest pair among the points (repeated points are at distance 0)
+    pub fn new(points: &[Point2D]) -> Self {
+        let mut result = Self {
+            pair: None,
+            distance_squared: f64::INFINITY,
+        };
+        let mut sorted_x: Vec<&Point2D> = points.iter().collect();
+        sorted_x.sort_by(by_x);
+        let mut sorted_y = sorted_x.clone();
+        let mut aux = Vec::with_capacity(points.len());
+        result.closest(&sorted_x, &mut sorted_y, &mut aux);
+        result
+    }
+
+    // the closest pair in sorted_x, which has to hold the same points as sorted_y;
+    // leaves sorted_y sorted by y
+    fn closest<'a>(
+        &mut self,
+        sorted_x: &[&'a Point2D],
+        sorted_y: &mut [&'a Point2D],
+        aux: &mut Vec<&'a Point2D>,
+    ) {
+        let n = sorted_x.len();
+        if n <= 1 {
+            return;
+        }
+        let mid = n / 2;
+        let median = sorted_x[mid].x();
+        self.closest(&sorted_x[..mid], &mut sorted_y[..mid], aux);
+        self.closest(&sorted_x[mid..], &mut sorted_y[mid..], aux);
+
+        // merge the halves by y
+        aux.clear();
+        let (mut i, mut j) = (0, mid);
+        while i < mid || j < n {
+            if j == n || (i < mid && sorted_y[i].y() <= sorted_y[j].y()) {
+                aux.push(sorted_y[i]);
+                i += 1;
+            } else {
+                aux.push(sorted_y[j]);
+                j += 1;
+            }
+        }
+        sorted_y.copy_from_slice(aux);
+
+        // the strip within delta of the split line, in order of y
+        aux.clear();
+        for p in sorted_y.iter() {
+            let dx = p.x() - median;
+            if dx * dx >= self.distance_squared {
+                continue;
+            }
+            for q in aux.iter().rev() {
+                let dy = p.y() - q.y();
+                if dy * dy >= self.distance_squared {
+                    break;
+                }
+                let d = p.distance_squared_to(q);
+                if d < self.distance_squared {
+                    self.distance_squared = d;
+                    self.pair = Some(((*q).clone(), (*p).clone()));
+                }
+            }
+            aux.push(p);
+        }
+    }
+
+    // one point of the closest pair; None if there are fewer than two points
+    pub fn either(&self) -> Option<&Point2D> {
+        self.pair.as_ref().map(|(p, _)| p)
+    }
+
+    // the other point of the closest pair; None if there are fewer than two points
+    pub fn other(&self) -> Option<&Point2D> {
+        self.pair.as_ref().map(|(_, q)| q)
+    }
+
+    // the distance between the closest pair; infinity if there are fewer than two points
+    pub fn distance(&self) -> f64 {
+        self.distance_squared.sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn brute_force(points: &[Point2D]) -> f64 {
+        let mut best = f64::INFINITY;
+        for (i, p) in points.iter().enumerate() {
+            for q in points[i + 1..].iter() {
+                best = best.min(p.distance_squared_to(q));
+            }
+        }
+        best.sqrt()
+    }
+
+    #[test]
+    fn test() {
+        let mut rng = StdRng::seed_from_u64(49);
+        for n in [0, 1, 2, 3, 4, 5, 10, 100, 1000] {
+            for grid in [false, true] {
+                // a coarse grid has plenty of repeats and equal distances
+                let points: Vec<Point2D> = (0..n)
+                    .map(|_| {
+                        if grid {
+                            Point2D::new(rng.gen_range(0..40) as f64, rng.gen_range(0..40) as f64)
+                        } else {
+                            Point2D::new(rng.gen(), rng.gen())
+                        }
+                    })
+                    .collect();
+                let cp = ClosestPair::new(&points);
+                assert_eq!(cp.distance(), brute_force(&points), "{n} {grid}");
+                match (cp.either(), cp.other()) {
+                    (Some(p), Some(q)) => {
+                        assert_eq!(p.distance_to(q), cp.distance());
+                        assert!(points.contains(p) && points.contains(q));
+                    }
+                    (None, None) => assert!(n < 2),
+                    _ => unreachable!(),
+                }
+            }
+        }
+        // all on one vertical line, so every point is on the split
+        let line: Vec<Point2D> = (0..100)
+            .map(|i| Point2D::new(0.5, (i * i) as f64))
+            .collect();
+        assert_eq!(ClosestPair::new(&line).distance(), 1.0);
+    }
+}
@@ -0,0 +1,173 @@
+// Convex hulls of a set of points, by two O(n log n) algorithms. Both return the
+// vertices of the hull counterclockwise, starting from the smallest point (lowest,
+// then leftmost), without repeated points or points in the middle of an edge: no
+// points give an empty hull, and points all at one spot a hull of one.
+use crate::util::point2d::Point2D;
+
+/* Graham scan (as in Sedgewick and Wayne's GrahamScan). The smallest point p is on
+the hull. Sort the others by polar angle around p, those at the same angle nearest
+first (a stable sort after sorting by Point2D's order does that), and walk them in
+order, keeping a stack of the hull so far: a point that would make a clockwise or
+straight turn pops the stack until it doesn't. Repeated points are dropped first: a
+copy of p is at no angle to it, so the polar order isn't a total order with one. */
+pub fn graham_scan(points: &[Point2D]) -> Vec<Point2D> {
+    let mut a = points.to_vec();
+    a.sort();
+    a.dedup();
+    let Some(p) = a.first().cloned() else {
+        return vec![];
+    };
+    a[1..].sort_by(p.polar_order());
+
+    let mut hull = vec![p.clone()];
+    if a.len() == 1 {
+        return hull;
+    }
+    // the first point not collinear with p and a[1]; the one before it is the
+    // furthest of those in a[1]'s direction
+    let Some(k2) = (2..a.len()).find(|i| Point2D::ccw(&p, &a[1], &a[*i]) != 0) else {
+        hull.push(a[a.len() - 1].clone());
+        return hull;
+    };
+    hull.push(a[k2 - 1].clone());
+    for q in a.drain(k2..) {
+        let mut top = hull.pop().unwrap();
+        while Point2D::ccw(&hull[hull.len() - 1], &top, &q) <= 0 {
+            top = hull.pop().unwrap();
+        }
+        hull.push(top);
+        hull.push(q);
+    }
+    hull
+}
+
+/* Andrew's monotone chain. Sort the points by x (then y), and build the lower hull
+left to right and the upper hull right to left, each with the same stack as the
+Graham scan. Only ccw is needed, no angles. */
+pub fn monotone_chain(points: &[Point2D]) -> Vec<Point2D> {
+    let mut a = points.to_vec();
+    a.sort_by(|p, q| p.x().total_cmp(&q.x()).then(p.y().total_cmp(&q.y())));
+    a.dedup();
+    if a.len() < 3 {
+        a.sort();
+        return a;
+    }
+    // pop while the last two points and q don't turn counterclockwise
+    let push = |hull: &mut Vec<Point2D>, q: &Point2D, floor: usize| {
+        while hull.len() >= floor
+            && Point2D::ccw(&hull[hull.len() - 2], &hull[hull.len() - 1], q) <= 0
+        {
+            hull.pop();
+        }
+        hull.push(q.clone());
+    };
+    let mut hull: Vec<Point2D> = Vec::with_capacity(a.len() + 1);
+    for q in a.iter() {
+        push(&mut hull, q, 2);
+    }
+    // the upper hull starts from the last point of the lower one, and mustn't pop it
+    let floor = hull.len() + 1;
+    for q in a.iter().rev().skip(1) {
+        push(&mut hull, q, floor);
+    }
+    // the last point is the first again
+    hull.pop();
+    // start at the smallest point, as graham_scan does
+    let start = (0..hull.len())
+        .min_by(|i, j| hull[*i].cmp(&hull[*j]))
+        .unwrap();
+    hull.rotate_left(start);
+    hull
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{geometry::polygon::Polygon, util::region::Region};
+
+    // is hull convex and counterclockwise, with every point inside or on it?
+    fn check(points: &[Point2D], hull: &[Point2D]) {
+        let n = hull.len();
+        for i in 0..n {
+            let (a, b, c) = (&hull[i], &hull[(i + 1) % n], &hull[(i + 2) % n]);
+            if n > 2 {
+                assert_eq!(Point2D::ccw(a, b, c), 1);
+            }
+            assert!(points.contains(a));
+        }
+        if n > 0 {
+            assert_eq!(hull[0], *points.iter().min().unwrap());
+            let polygon = Polygon::new(hull.to_vec());
+            assert!(points.iter().all(|p| polygon.contains(p)));
+        }
+    }
+
+    #[test]
+    fn test() {
+        let mut rng = StdRng::seed_from_u64(50);
+        for n in [0, 1, 2, 3, 10, 100, 1000] {
+            for grid in [false, true] {
+                let points: Vec<Point2D> = (0..n)
+                    .map(|_| {
+                        if grid {
+                            Point2D::new(rng.gen_range(0..5) as f64, rng.gen_range(0..5) as f64)
+                        } else {
+                            Point2D::new(rng.gen(), rng.gen())
+                        }
+                    })
+                    .collect();
+                let hull = graham_scan(&points);
+                check(&points, &hull);
+                assert_eq!(monotone_chain(&points), hull, "{n} {grid}");
+            }
+        }
+        // a square with points along its edges and inside
+        let mut points = vec![];
+        for i in 0..=4 {
+            for j in 0..=4 {
+                points.push(Point2D::new(i as f64, j as f64));
+            }
+        }
+        let corners = vec![
+            Point2D::new(0.0, 0.0),
+            Point2D::new(4.0, 0.0),
+            Point2D::new(4.0, 4.0),
+            Point2D::new(0.0, 4.0),
+        ];
+        assert_eq!(graham_scan(&points), corners);
+        assert_eq!(monotone_chain(&points), corners);
+        // all collinear, or all the same
+        let line: Vec<Point2D> = (0..5)
+            .map(|i| Point2D::new(4.0 - i as f64, i as f64))
+            .collect();
+        let ends = vec![Point2D::new(4.0, 0.0), Point2D::new(0.0, 4.0)];
+        assert_eq!(graham_scan(&line), ends);
+        assert_eq!(monotone_chain(&line), ends);
+        let same = vec![Point2D::new(1.0, 1.0); 3];
+        assert_eq!(graham_scan(&same), same[..1]);
+        assert_eq!(monotone_chain(&same), same[..1]);
+    }
+
+    #[test]
+    fn test_repeated_lowest() {
+        // copies of the lowest point compare equal to every other point in polar order,
+        // which scrambled the sort once it was big enough not to be an insertion sort
+        let mut rng = StdRng::seed_from_u64(51);
+        for _ in 0..200 {
+            let (n, side) = (rng.gen_range(3..400), rng.gen_range(3..60));
+            let mut points: Vec<Point2D> = (0..n)
+                .map(|_| Point2D::new(rng.gen_range(0..side) as f64, rng.gen_range(0..side) as f64))
+                .collect();
+            let lowest = points.iter().min().unwrap().clone();
+            for _ in 0..rng.gen_range(1..40) {
+                let i = rng.gen_range(0..=points.len());
+                points.insert(i, lowest.clone());
+            }
+            let hull = graham_scan(&points);
+            check(&points, &hull);
+            assert_eq!(hull, monotone_chain(&points), "{n} {side}");
+        }
+    }
+}
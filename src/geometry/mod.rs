@@ -0,0 +1,63 @@
+use plotters::style::{BLUE, RED};
+
+use crate::{kdtrees::read_points, util::std_draw::Plot};
+
+use self::{closest_pair::ClosestPair, convex_hull::graham_scan, polygon::Polygon};
+
+pub mod closest_pair;
+pub mod convex_hull;
+pub mod nearest_neighbors;
+pub mod polygon;
+
+pub fn run_closest_pair(filename: String) -> anyhow::Result<()> {
+    let points = read_points(filename)?;
+    let cp = ClosestPair::new(&points);
+
+    let mut plot = Plot::new()?;
+    plot.set_x_scale(0.0, 1.0);
+    plot.set_y_scale(0.0, 1.0);
+
+    for p in points.iter() {
+        p.draw(&mut plot)?;
+    }
+    if let (Some(p), Some(q)) = (cp.either(), cp.other()) {
+        println!("closest pair {:?} {:?} at {}", p, q, cp.distance());
+        p.draw_match(&mut plot)?;
+        q.draw_match(&mut plot)?;
+        plot.line(p.x(), p.y(), q.x(), q.y())?;
+    }
+
+    plot.present()?;
+
+    anyhow::Ok(())
+}
+
+pub fn run_convex_hull(filename: String) -> anyhow::Result<()> {
+    let points = read_points(filename)?;
+    let hull = Polygon::new(graham_scan(&points));
+    println!(
+        "hull of {} points: {} vertices, area {}, perimeter {}",
+        points.len(),
+        hull.vertices().len(),
+        hull.area(),
+        hull.perimeter()
+    );
+
+    let mut plot = Plot::new()?;
+    plot.set_x_scale(0.0, 1.0);
+    plot.set_y_scale(0.0, 1.0);
+
+    for p in points.iter() {
+        p.draw(&mut plot)?;
+    }
+    plot.set_pen_color(RED);
+    hull.draw(&mut plot)?;
+    plot.set_pen_color(BLUE);
+    if let Some(c) = hull.centroid() {
+        c.draw_match(&mut plot)?;
+    }
+
+    plot.present()?;
+
+    anyhow::Ok(())
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use crate::{
+    kdtrees::{kdtree::SplitHV, kdtree_st::KdTreeST},
+    util::point2d::Point2D,
+};
+
+/* For every point, the index of its nearest other point: None for a lone point, and
+of equally near ones, the smallest point. Repeated points are each other's nearest
+neighbors, at distance 0: each copy gets the first of the others.
+
+The distinct points go in a balanced 2d-tree, each with the indices of its copies, so
+any number of repeats is one node. Asking for the two nearest entries of a point gives
+itself and its neighbor, which only a point without copies needs. That's n searches of
+O(log n) each for points that are spread out. */
+pub fn all_nearest_neighbors(points: &[Point2D]) -> Vec<Option<usize>> {
+    let mut copies: HashMap<&Point2D, Vec<usize>> = HashMap::with_capacity(points.len());
+    for (i, p) in points.iter().enumerate() {
+        copies.entry(p).or_default().push(i);
+    }
+    let entries = copies.into_iter().map(|(p, c)| (p.clone(), c)).collect();
+    let tree: KdTreeST<Vec<usize>> = KdTreeST::balanced(SplitHV::V, entries);
+    let mut neighbors = vec![None; points.len()];
+    for (p, copies) in tree.all() {
+        if let [i] = copies[..] {
+            neighbors[i] = tree.nearest_k(p.clone(), 2).get(1).map(|(_, c)| c[0]);
+        } else {
+            for &i in copies {
+                neighbors[i] = copies.iter().copied().find(|j| *j != i);
+            }
+        }
+    }
+    neighbors
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test() {
+        let mut rng = StdRng::seed_from_u64(50);
+        for n in [0, 1, 2, 3, 50, 500] {
+            for grid in [false, true] {
+                let points: Vec<Point2D> = (0..n)
+                    .map(|_| {
+                        if grid {
+                            Point2D::new(rng.gen_range(0..20) as f64, rng.gen_range(0..20) as f64)
+                        } else {
+                            Point2D::new(rng.gen(), rng.gen())
+                        }
+                    })
+                    .collect();
+                let neighbors = all_nearest_neighbors(&points);
+                assert_eq!(neighbors.len(), n);
+                for (i, p) in points.iter().enumerate() {
+                    let expected = (0..n)
+                        .filter(|j| *j != i)
+                        .map(|j| p.distance_squared_to(&points[j]))
+                        .min_by(f64::total_cmp);
+                    let got = neighbors[i].map(|j| {
+                        assert_ne!(i, j);
+                        p.distance_squared_to(&points[j])
+                    });
+                    assert_eq!(got, expected, "{n} {grid} {i}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_repeats() {
+        // as from a GPS that keeps reporting the same fix
+        let n = 100_000;
+        let mut points = vec![Point2D::new(0.5, 0.5); n];
+        points.push(Point2D::new(0.75, 0.5));
+        let neighbors = all_nearest_neighbors(&points);
+        assert_eq!(neighbors[0], Some(1));
+        for (i, j) in neighbors.iter().enumerate().take(n).skip(1) {
+            assert_eq!(*j, Some(0), "{i}");
+        }
+        assert_eq!(neighbors[n], Some(0));
+    }
+}
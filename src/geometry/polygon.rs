@@ -0,0 +1,156 @@
+use crate::util::{point2d::Point2D, rect_hv::RectHV, region::Region, std_draw::Plot};
+
+/// A simple polygon (its edges don't cross), given by its vertices in order around
+/// it in either direction, including its boundary. Unlike ConvexPolygon it may be
+/// concave, so it only offers its bounding box to prune range searches.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Polygon {
+    vertices: Vec<Point2D>,
+    bounds: RectHV,
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<Point2D>) -> Self {
+        assert!(!vertices.is_empty());
+        let bounds = vertices.iter().skip(1).fold(
+            RectHV::new(
+                vertices[0].x(),
+                vertices[0].y(),
+                vertices[0].x(),
+                vertices[0].y(),
+            ),
+            |b, v| b.union(&RectHV::new(v.x(), v.y(), v.x(), v.y())),
+        );
+        Self { vertices, bounds }
+    }
+
+    pub fn vertices(&self) -> &[Point2D] {
+        &self.vertices
+    }
+
+    // each edge as its two ends, the last one closing the polygon
+    fn edges(&self) -> impl Iterator<Item = (&Point2D, &Point2D)> {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+    }
+
+    // positive if the vertices go around counterclockwise, negative if clockwise
+    fn signed_area(&self) -> f64 {
+        // the shoelace formula
+        self.edges()
+            .map(|(a, b)| a.x() * b.y() - b.x() * a.y())
+            .sum::<f64>()
+            / 2.0
+    }
+
+    pub fn area(&self) -> f64 {
+        self.signed_area().abs()
+    }
+
+    pub fn perimeter(&self) -> f64 {
+        self.edges().map(|(a, b)| a.distance_to(b)).sum()
+    }
+
+    // the center of mass of the polygon's area; None if it has no area
+    pub fn centroid(&self) -> Option<Point2D> {
+        let area = self.signed_area();
+        if area == 0.0 {
+            return None;
+        }
+        let (mut cx, mut cy) = (0.0, 0.0);
+        for (a, b) in self.edges() {
+            let cross = a.x() * b.y() - b.x() * a.y();
+            cx += (a.x() + b.x()) * cross;
+            cy += (a.y() + b.y()) * cross;
+        }
+        Some(Point2D::new(cx / (6.0 * area), cy / (6.0 * area)))
+    }
+
+    // draw the edges to standard draw
+    pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        for (a, b) in self.edges() {
+            a.draw_to(b, plot)?;
+        }
+        Ok(())
+    }
+}
+
+// is p on the segment a-b?
+fn on_segment(a: &Point2D, b: &Point2D, p: &Point2D) -> bool {
+    Point2D::ccw(a, b, p) == 0
+        && a.x().min(b.x()) <= p.x()
+        && p.x() <= a.x().max(b.x())
+        && a.y().min(b.y()) <= p.y()
+        && p.y() <= a.y().max(b.y())
+}
+
+impl Region for Polygon {
+    fn contains(&self, p: &Point2D) -> bool {
+        /* The winding number: how many times the boundary goes around p. Count the
+        edges that cross the horizontal line through p going up with p on their left,
+        less those going down with p on their right. Any point of the boundary counts
+        as inside, and is checked for first since it would make the count ambiguous. */
+        if !self.bounds.contains(p) {
+            return false;
+        }
+        let mut winding = 0;
+        for (a, b) in self.edges() {
+            if on_segment(a, b, p) {
+                return true;
+            }
+            if a.y() <= p.y() {
+                if b.y() > p.y() && Point2D::ccw(a, b, p) > 0 {
+                    winding += 1;
+                }
+            } else if b.y() <= p.y() && Point2D::ccw(a, b, p) < 0 {
+                winding -= 1;
+            }
+        }
+        winding != 0
+    }
+
+    fn intersects(&self, rect: &RectHV) -> bool {
+        self.bounds.intersects(rect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        // an L of three unit squares, given clockwise
+        let l = Polygon::new(vec![
+            Point2D::new(0.0, 0.0),
+            Point2D::new(0.0, 2.0),
+            Point2D::new(1.0, 2.0),
+            Point2D::new(1.0, 1.0),
+            Point2D::new(2.0, 1.0),
+            Point2D::new(2.0, 0.0),
+        ]);
+        assert_eq!(l.area(), 3.0);
+        assert_eq!(l.perimeter(), 8.0);
+        // the mean of the squares' centers
+        let c = l.centroid().unwrap();
+        assert!((c.x() - 5.0 / 6.0).abs() < 1e-12 && (c.y() - 5.0 / 6.0).abs() < 1e-12);
+
+        assert!(l.contains(&Point2D::new(0.5, 1.5)));
+        assert!(l.contains(&Point2D::new(1.5, 0.5)));
+        // in the notch
+        assert!(!l.contains(&Point2D::new(1.5, 1.5)));
+        // on the boundary, at a vertex and level with one
+        assert!(l.contains(&Point2D::new(1.0, 1.5)));
+        assert!(l.contains(&Point2D::new(2.0, 1.0)));
+        assert!(!l.contains(&Point2D::new(-0.5, 1.0)));
+        assert!(!l.contains(&Point2D::new(3.0, 0.5)));
+        assert!(l.intersects(&RectHV::new(1.5, 1.5, 3.0, 3.0)));
+        assert!(!l.intersects(&RectHV::new(2.5, 2.5, 3.0, 3.0)));
+
+        let flat = Polygon::new(vec![Point2D::new(0.0, 0.0), Point2D::new(1.0, 1.0)]);
+        assert_eq!(flat.area(), 0.0);
+        assert_eq!(flat.centroid(), None);
+        assert!(flat.contains(&Point2D::new(0.5, 0.5)));
+    }
+}
@@ -1,308 +1,556 @@
-use std::time::Instant;
+use std::io::{Read, Write};
 
-use crate::util::{point2d::Point2D, rect_hv::RectHV, std_draw::Plot};
+use plotters::style::{BLUE, RED};
 
+use crate::{
+    error::AlgoError,
+    util::{point2d::Point2D, rect_hv::RectHV, region::Region, std_draw::Plot},
+};
+
+use super::{
+    kdtree_st::{self, KdTreeST},
+    persist::{Decoder, Encoder},
+    point_index::PointIndex,
+    query_stats::{QueryObserver, QueryStats},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SplitHV {
     H,
     V,
 }
 
+impl SplitHV {
+    // the direction of the level below
+    pub(super) fn flip(self) -> Self {
+        match self {
+            SplitHV::H => SplitHV::V,
+            SplitHV::V => SplitHV::H,
+        }
+    }
+
+    // the coordinate compared at a node split this way
+    pub(super) fn coord(self, p: &Point2D) -> f64 {
+        match self {
+            SplitHV::H => p.y(),
+            SplitHV::V => p.x(),
+        }
+    }
+
+    // draw the split line through p across rect, as KdTreeVisualizer does: red
+    // for vertical splits, blue for horizontal ones. Leaves the pen that color.
+    pub(super) fn draw(self, p: &Point2D, rect: &RectHV, plot: &mut Plot) -> anyhow::Result<()> {
+        match self {
+            SplitHV::H => {
+                plot.set_pen_color(BLUE);
+                plot.line(rect.xmin(), p.y(), rect.xmax(), p.y())
+            }
+            SplitHV::V => {
+                plot.set_pen_color(RED);
+                plot.line(p.x(), rect.ymin(), p.x(), rect.ymax())
+            }
+        }
+    }
+
+    // the rectangles of the left and right subtrees of a node at p
+    pub(super) fn split_rect(self, p: &Point2D, rect: &RectHV) -> (RectHV, RectHV) {
+        match self {
+            SplitHV::H => (
+                RectHV::new(rect.xmin(), rect.ymin(), rect.xmax(), p.y()),
+                RectHV::new(rect.xmin(), p.y(), rect.xmax(), rect.ymax()),
+            ),
+            SplitHV::V => (
+                RectHV::new(rect.xmin(), rect.ymin(), p.x(), rect.ymax()),
+                RectHV::new(p.x(), rect.ymin(), rect.xmax(), rect.ymax()),
+            ),
+        }
+    }
+}
+
+// file type of a saved KdTree
+const MAGIC: &[u8; 4] = b"KDTR";
+
+/// A set of points on a 2d-tree: a KdTreeST without values.
 pub struct KdTree {
-    split: SplitHV,
-    point: Option<Point2D>,
-    left: Option<Box<KdTree>>,
-    right: Option<Box<KdTree>>,
+    st: KdTreeST<()>,
 }
 
 impl KdTree {
     // construct an empty tree of points
     pub fn new(split: SplitHV) -> Self {
         Self {
-            split,
-            point: None,
-            left: None,
-            right: None,
+            st: KdTreeST::with_split(split),
+        }
+    }
+
+    // construct a perfectly balanced tree of the points (repeated points are kept once)
+    pub fn from_points(points: Vec<Point2D>) -> Self {
+        let entries = points.into_iter().map(|p| (p, ())).collect();
+        Self {
+            st: KdTreeST::balanced(SplitHV::V, entries),
         }
     }
 
+    // rebuild the tree so that it is perfectly balanced
+    pub fn rebalance(&mut self) {
+        self.st.rebalance();
+    }
+
+    // write the tree in the binary format of persist, shape and all
+    pub fn save<W: Write>(&self, w: W) -> Result<(), AlgoError> {
+        let mut enc = Encoder::new(w, MAGIC)?;
+        self.st.encode(&mut enc)?;
+        Ok(enc.finish()?)
+    }
+
+    // read a tree written by save, exactly as it was
+    pub fn load<R: Read>(r: R) -> Result<Self, AlgoError> {
+        let st = KdTreeST::decode(Decoder::new(r, MAGIC)?)?;
+        Ok(Self { st })
+    }
+
+    // number of nodes on the longest path from the root to a leaf (0 for an empty tree)
+    pub fn height(&self) -> usize {
+        self.st.height()
+    }
+
     // is the tree empty?
     pub fn is_empty(&self) -> bool {
-        self.point.is_none()
+        self.st.is_empty()
     }
 
     // number of points in the tree
     pub fn size(&self) -> usize {
-        self.all().len()
+        self.st.size()
     }
 
-    // add the point to the tree
+    // add the point to the tree, unless it is already there (as PointSET does;
+    // insert used to add repeats)
     pub fn insert(&mut self, p: Point2D) {
-        match &self.point {
-            Some(point) => match self.split {
-                SplitHV::H => {
-                    if p.y() < point.y() {
-                        // go left
-                        if self.left.is_none() {
-                            self.left = Some(Box::new(KdTree::new(SplitHV::V)));
-                        }
-                        self.left.as_mut().unwrap().insert(p);
-                    } else {
-                        // go right
-                        if self.right.is_none() {
-                            self.right = Some(Box::new(KdTree::new(SplitHV::V)));
-                        }
-                        self.right.as_mut().unwrap().insert(p);
-                    }
-                }
-                SplitHV::V => {
-                    if p.x() < point.x() {
-                        // go left
-                        if self.left.is_none() {
-                            self.left = Some(Box::new(KdTree::new(SplitHV::H)));
-                        }
-                        self.left.as_mut().unwrap().insert(p);
-                    } else {
-                        // go right
-                        if self.right.is_none() {
-                            self.right = Some(Box::new(KdTree::new(SplitHV::H)));
-                        }
-                        self.right.as_mut().unwrap().insert(p);
-                    }
-                }
-            },
-            None => self.point = Some(p),
-        }
+        self.st.put(p, ());
+    }
+
+    // the smallest rectangle containing every point inserted so far (removing points
+    // doesn't shrink it); None until the first insert
+    pub fn bounds(&self) -> Option<&RectHV> {
+        self.st.bounds()
     }
 
     // does the tree contain point p?
     pub fn contains(&self, p: Point2D) -> bool {
-        match &self.point {
-            Some(point) => {
-                if *point == p {
-                    true
-                } else {
-                    match self.split {
-                        SplitHV::H => {
-                            if p.y() < point.y() {
-                                // go left
-                                match &self.left {
-                                    Some(t) => t.contains(p),
-                                    None => false,
-                                }
-                            } else {
-                                // go right
-                                match &self.right {
-                                    Some(t) => t.contains(p),
-                                    None => false,
-                                }
-                            }
-                        }
-                        SplitHV::V => {
-                            if p.x() < point.x() {
-                                // go left
-                                match &self.left {
-                                    Some(t) => t.contains(p),
-                                    None => false,
-                                }
-                            } else {
-                                // go right
-                                match &self.right {
-                                    Some(t) => t.contains(p),
-                                    None => false,
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            None => false,
+        self.st.contains(p)
+    }
+
+    // contains, reporting the nodes on the path to the observer
+    pub fn contains_observed<O: QueryObserver>(&self, p: Point2D, observer: O) -> bool {
+        self.st.contains_observed(p, observer)
+    }
+
+    // contains, with the cost of the search
+    pub fn contains_with_stats(&self, p: Point2D) -> (bool, QueryStats) {
+        self.st.contains_with_stats(p)
+    }
+
+    // remove the point p from the tree; false if it wasn't there
+    pub fn remove(&mut self, p: Point2D) -> bool {
+        self.st.remove(p).is_some()
+    }
+
+    // move the point old to new; false (and no insert) if old wasn't in the tree
+    pub fn update(&mut self, old: Point2D, new: Point2D) -> bool {
+        if self.remove(old) {
+            self.insert(new);
+            true
+        } else {
+            false
         }
     }
 
     // draw all points to standard draw
     pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
-        for p in self.all() {
-            p.draw(plot)?;
-        }
-        Ok(())
-    }
-
-    // all points that are inside the rectangle (or on the boundary)
-    pub fn range(&self, query_rect: RectHV) -> Vec<&Point2D> {
-        /* Range search. To find all points contained in a given query rectangle,
-        start at the root and recursively search for points in both subtrees using the following pruning rule:
-        if the query rectangle does not intersect the rectangle corresponding to a node,
-        there is no need to explore that node (or its subtrees).
-        A subtree is searched only if it might contain a point contained in the query rectangle. */
-        let mut points = vec![];
-        let rect = RectHV::new(0.0, 0.0, 1.0, 1.0);
-        self.collect_range_points(&query_rect, &rect, &mut points);
-        points
-    }
-
-    fn collect_range_points<'a>(
-        &'a self,
-        query_rect: &RectHV,
-        rect: &RectHV,
-        points: &mut Vec<&'a Point2D>,
-    ) {
-        if let Some(p) = &self.point {
-            if query_rect.contains(p) {
-                points.push(p);
-            }
-            if query_rect.intersects(rect) {
-                // now update the rects and contiue if they intersect
-                if let Some(t) = &self.left {
-                    match &self.split {
-                        SplitHV::H => {
-                            let new_rect =
-                                RectHV::new(rect.xmin(), rect.ymin(), rect.xmax(), p.y());
-                            t.collect_range_points(query_rect, &new_rect, points);
-                        }
-                        SplitHV::V => {
-                            let new_rect =
-                                RectHV::new(rect.xmin(), rect.ymin(), p.x(), rect.ymax());
-                            t.collect_range_points(query_rect, &new_rect, points);
-                        }
-                    }
-                }
-                if let Some(t) = &self.right {
-                    match &self.split {
-                        SplitHV::H => {
-                            let new_rect =
-                                RectHV::new(rect.xmin(), p.y(), rect.xmax(), rect.ymax());
-                            t.collect_range_points(query_rect, &new_rect, points);
-                        }
-                        SplitHV::V => {
-                            let new_rect =
-                                RectHV::new(p.x(), rect.ymin(), rect.xmax(), rect.ymax());
-                            t.collect_range_points(query_rect, &new_rect, points);
-                        }
-                    }
-                }
-            }
-        }
+        self.st.draw(plot)
+    }
+
+    // draw all points with the split line of each node across the node's rectangle
+    pub fn draw_splits(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        self.st.draw_splits(plot)
+    }
+
+    // all points that are inside the region (or on the boundary)
+    pub fn range<R: Region>(&self, region: R) -> Vec<&Point2D> {
+        self.range_iter(region).collect()
+    }
+
+    // lazily iterate over the points inside the region, same pruning as range
+    pub fn range_iter<R: Region>(&self, region: R) -> RangeIter<'_, R> {
+        RangeIter(self.st.range_iter(region))
+    }
+
+    // range_iter, reporting what the search does to the observer
+    pub fn range_iter_observed<R: Region, O: QueryObserver>(
+        &self,
+        region: R,
+        observer: O,
+    ) -> RangeIter<'_, R, O> {
+        RangeIter(self.st.range_iter_observed(region, observer))
+    }
+
+    // range, with the cost of the search
+    pub fn range_with_stats<R: Region>(&self, region: R) -> (Vec<&Point2D>, QueryStats) {
+        QueryStats::measure(|stats| self.range_iter_observed(region, stats).collect())
     }
 
     // a nearest neighbor in the set to point p; null if the set is empty
     pub fn nearest(&self, p: Point2D) -> Option<&Point2D> {
-        /* Nearest-neighbor search. To find a closest point to a given query point,
-        start at the root and recursively search in both subtrees using the following pruning rule:
-        if the closest point discovered so far is closer than the distance between the query point and the rectangle
-        corresponding to a node, there is no need to explore that node (or its subtrees).
-        That is, search a node only only if it might contain a point that is closer than the best one found so far.
-        The effectiveness of the pruning rule depends on quickly finding a nearby point.
-        To do this, organize the recursive method so that when there are two possible subtrees to go down,
-        you always choose the subtree that is on the same side of the splitting line as the query point as the
-        first subtree to explore—the closest point found while exploring the first subtree may enable pruning of
-        the second subtree. */
-        if let Some(best_point) = &self.point {
-            let start = Instant::now();
-            let rect = RectHV::new(0.0, 0.0, 1.0, 1.0);
-            let (bp, _) = self.inner_nearest(&p, &rect, best_point, f64::MAX);
-            let duration = start.elapsed();
-            println!("{:?}", duration);
-            Some(bp)
-        } else {
-            None
+        self.st.nearest(p).map(|(p, _)| p)
+    }
+
+    // nearest, reporting what the search does to the observer
+    pub fn nearest_observed<O: QueryObserver>(&self, p: Point2D, observer: O) -> Option<&Point2D> {
+        self.st.nearest_observed(p, observer).map(|(p, _)| p)
+    }
+
+    // nearest, with the cost of the search
+    pub fn nearest_with_stats(&self, p: Point2D) -> (Option<&Point2D>, QueryStats) {
+        QueryStats::measure(|stats| self.nearest_observed(p, stats))
+    }
+
+    // a point within a factor (1 + eps) of the distance to the nearest neighbor of p;
+    // null if the set is empty
+    pub fn nearest_approx(&self, p: Point2D, eps: f64) -> Option<&Point2D> {
+        self.st.nearest_approx(p, eps).map(|(p, _)| p)
+    }
+
+    // nearest_approx, with the cost of the search
+    pub fn nearest_approx_with_stats(
+        &self,
+        p: Point2D,
+        eps: f64,
+    ) -> (Option<&Point2D>, QueryStats) {
+        let (nearest, stats) = self.st.nearest_approx_with_stats(p, eps);
+        (nearest.map(|(p, _)| p), stats)
+    }
+
+    // nearest for every query point, spreading the queries over all CPUs
+    pub fn nearest_many(&self, queries: &[Point2D]) -> Vec<Option<&Point2D>> {
+        self.st
+            .nearest_many(queries)
+            .into_iter()
+            .map(|n| n.map(|(p, _)| p))
+            .collect()
+    }
+
+    // nearest_approx for every query point, spreading the queries over all CPUs
+    pub fn nearest_approx_many(&self, queries: &[Point2D], eps: f64) -> Vec<Option<&Point2D>> {
+        self.st
+            .nearest_approx_many(queries, eps)
+            .into_iter()
+            .map(|n| n.map(|(p, _)| p))
+            .collect()
+    }
+
+    // the k points in the set closest to p, nearest first (ties go to the smaller point)
+    pub fn nearest_k(&self, p: Point2D, k: usize) -> Vec<&Point2D> {
+        self.st
+            .nearest_k(p, k)
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect()
+    }
+
+    pub fn all(&self) -> Vec<&Point2D> {
+        self.st.all().into_iter().map(|(p, _)| p).collect()
+    }
+}
+
+impl PointIndex for KdTree {
+    fn is_empty(&self) -> bool {
+        KdTree::is_empty(self)
+    }
+
+    fn size(&self) -> usize {
+        KdTree::size(self)
+    }
+
+    fn insert(&mut self, p: Point2D) {
+        KdTree::insert(self, p)
+    }
+
+    fn remove(&mut self, p: Point2D) -> bool {
+        KdTree::remove(self, p)
+    }
+
+    fn contains(&self, p: Point2D) -> bool {
+        KdTree::contains(self, p)
+    }
+
+    fn range(&self, region: &dyn Region) -> Vec<&Point2D> {
+        KdTree::range(self, region)
+    }
+
+    fn nearest(&self, p: Point2D) -> Option<&Point2D> {
+        KdTree::nearest(self, p)
+    }
+
+    fn nearest_k(&self, p: Point2D, k: usize) -> Vec<&Point2D> {
+        KdTree::nearest_k(self, p, k)
+    }
+}
+
+pub struct RangeIter<'a, R, O = ()>(kdtree_st::RangeIter<'a, (), R, O>);
+
+impl<'a, R: Region, O: QueryObserver> Iterator for RangeIter<'a, R, O> {
+    type Item = &'a Point2D;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(p, _)| p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        kdtrees::point_set::PointSET,
+        util::region::{Circle, ConvexPolygon},
+    };
+
+    fn random_point(rng: &mut StdRng) -> Point2D {
+        Point2D::new(rng.gen(), rng.gen())
+    }
+
+    #[test]
+    fn test_nearest_k() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut tree = KdTree::new(SplitHV::V);
+        let mut set = PointSET::new();
+        assert!(tree.nearest_k(Point2D::new(0.5, 0.5), 3).is_empty());
+        for _ in 0..2000 {
+            let p = random_point(&mut rng);
+            tree.insert(p.clone());
+            set.insert(p);
+        }
+        for k in [0, 1, 2, 5, 17, 2000, 3000] {
+            for _ in 0..20 {
+                let q = random_point(&mut rng);
+                assert_eq!(tree.nearest_k(q.clone(), k), set.nearest_k(q, k));
+            }
         }
     }
 
-    fn inner_nearest<'a>(
-        &'a self,
-        query_point: &Point2D,
-        rect: &RectHV,
-        best_point: &'a Point2D,
-        best_distance: f64,
-    ) -> (&Point2D, f64) {
-        if let Some(p) = &self.point {
-            let mut d = p.distance_to(query_point);
-            let mut bp = if d < best_distance {
-                p
-            } else {
-                d = best_distance;
-                best_point
-            };
-
-            let mut went_left = false;
-            let mut went_right = false;
-
-            if self.left.is_some() && self.right.is_some() {
-                // choose the subtree on the same side of the splitting line as the query point
-                let go_left = match &self.split {
-                    SplitHV::H => query_point.y() < p.y(),
-                    SplitHV::V => query_point.x() < p.x(),
-                };
-
-                if go_left {
-                    if let Some(t) = &self.left {
-                        let new_rect = match &self.split {
-                            SplitHV::H => RectHV::new(rect.xmin(), rect.ymin(), rect.xmax(), p.y()),
-                            SplitHV::V => RectHV::new(rect.xmin(), rect.ymin(), p.x(), rect.ymax()),
-                        };
-                        if new_rect.distance_to(query_point) < d {
-                            (bp, d) = t.inner_nearest(query_point, &new_rect, bp, d);
-                        }
-                    }
-                    went_left = true;
-                } else {
-                    if let Some(t) = &self.right {
-                        let new_rect = match &self.split {
-                            SplitHV::H => RectHV::new(rect.xmin(), p.y(), rect.xmax(), rect.ymax()),
-                            SplitHV::V => RectHV::new(p.x(), rect.ymin(), rect.xmax(), rect.ymax()),
-                        };
-                        if new_rect.distance_to(query_point) < d {
-                            (bp, d) = t.inner_nearest(query_point, &new_rect, bp, d);
-                        }
-                    }
-                    went_right = true;
-                }
+    #[test]
+    fn test_nearest_k_ties() {
+        // a grid has lots of equally distant points
+        let mut tree = KdTree::new(SplitHV::V);
+        let mut set = PointSET::new();
+        for i in 0..10 {
+            for j in 0..10 {
+                let p = Point2D::new(i as f64 / 10.0, j as f64 / 10.0);
+                tree.insert(p.clone());
+                set.insert(p);
+            }
+        }
+        for q in [
+            Point2D::new(0.5, 0.5),
+            Point2D::new(0.45, 0.45),
+            Point2D::new(0.0, 0.35),
+        ] {
+            for k in 1..12 {
+                assert_eq!(tree.nearest_k(q.clone(), k), set.nearest_k(q.clone(), k));
             }
+        }
+    }
 
-            if !went_left {
-                if let Some(t) = &self.left {
-                    let new_rect = match &self.split {
-                        SplitHV::H => RectHV::new(rect.xmin(), rect.ymin(), rect.xmax(), p.y()),
-                        SplitHV::V => RectHV::new(rect.xmin(), rect.ymin(), p.x(), rect.ymax()),
-                    };
-                    if new_rect.distance_to(query_point) < d {
-                        (bp, d) = t.inner_nearest(query_point, &new_rect, bp, d);
+    #[test]
+    fn test_remove() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut tree = KdTree::new(SplitHV::V);
+        let mut set = PointSET::new();
+        assert!(!tree.remove(Point2D::new(0.5, 0.5)));
+        // coordinates on a coarse grid so that splits often tie
+        let grid_point = |rng: &mut StdRng| {
+            Point2D::new(
+                rng.gen_range(0..16) as f64 / 16.0,
+                rng.gen_range(0..16) as f64 / 16.0,
+            )
+        };
+        for step in 0..5000 {
+            let p = grid_point(&mut rng);
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    tree.insert(p.clone());
+                    set.insert(p);
+                }
+                2 => assert_eq!(tree.remove(p.clone()), set.remove(p)),
+                _ => {
+                    let q = grid_point(&mut rng);
+                    let moved = tree.update(p.clone(), q.clone());
+                    assert_eq!(moved, set.remove(p));
+                    if moved {
+                        set.insert(q);
                     }
                 }
             }
-
-            if !went_right {
-                if let Some(t) = &self.right {
-                    let new_rect = match &self.split {
-                        SplitHV::H => RectHV::new(rect.xmin(), p.y(), rect.xmax(), rect.ymax()),
-                        SplitHV::V => RectHV::new(p.x(), rect.ymin(), rect.xmax(), rect.ymax()),
-                    };
-                    if new_rect.distance_to(query_point) < d {
-                        (bp, d) = t.inner_nearest(query_point, &new_rect, bp, d);
-                    }
+            assert_eq!(tree.size(), set.size());
+            if step % 50 == 0 {
+                for p in set.all() {
+                    assert!(tree.contains(p.clone()));
                 }
+                let q = random_point(&mut rng);
+                assert_eq!(tree.nearest_k(q.clone(), 5), set.nearest_k(q, 5));
+                let rect = RectHV::new(0.2, 0.3, 0.6, 0.7);
+                let mut got = tree.range(&rect);
+                got.sort();
+                assert_eq!(got, set.range(&rect));
             }
-            (bp, d)
-        } else {
-            (best_point, best_distance)
         }
+        for p in set.all() {
+            assert!(tree.remove(p.clone()));
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.size(), 0);
     }
 
-    pub fn all(&self) -> Vec<&Point2D> {
-        let mut points = vec![];
-        self.collect_points(&mut points);
-        points
+    #[test]
+    fn test_insert_repeats() {
+        // a point that is already there is ignored, as in PointSET
+        let p = Point2D::new(0.5, 0.5);
+        let mut tree = KdTree::new(SplitHV::V);
+        for _ in 0..3 {
+            tree.insert(p.clone());
+        }
+        assert_eq!(tree.size(), 1);
+        assert_eq!(tree.height(), 1);
+        assert!(tree.remove(p.clone()));
+        assert!(!tree.contains(p.clone()));
+        assert!(tree.is_empty());
+        assert_eq!(KdTree::from_points(vec![p.clone(), p.clone(), p]).size(), 1);
     }
 
-    fn collect_points<'a>(&'a self, points: &mut Vec<&'a Point2D>) {
-        if let Some(p) = &self.point {
-            points.push(p);
-            if let Some(t) = &self.left {
-                t.collect_points(points);
-            }
-            if let Some(t) = &self.right {
-                t.collect_points(points);
+    #[test]
+    fn test_from_points() {
+        let mut rng = StdRng::seed_from_u64(4);
+        assert!(KdTree::from_points(vec![]).is_empty());
+        let points: Vec<Point2D> = (0..1000).map(|_| random_point(&mut rng)).collect();
+        let tree = KdTree::from_points(points.clone());
+        assert_eq!(tree.size(), 1000);
+        assert_eq!(tree.height(), 10);
+        for p in points.iter() {
+            assert!(tree.contains(p.clone()));
+        }
+
+        // presorted input with lots of ties: a line of points inserted one by one
+        let line: Vec<Point2D> = (0..1023)
+            .map(|i| Point2D::new(0.5, i as f64 / 1023.0))
+            .collect();
+        let mut tree = KdTree::new(SplitHV::V);
+        for p in line.iter() {
+            tree.insert(p.clone());
+        }
+        assert_eq!(tree.height(), 1023);
+        tree.rebalance();
+        assert!(tree.height() < 1023);
+        let balanced = KdTree::from_points(line.clone());
+        assert_eq!(balanced.height(), tree.height());
+        for p in line.iter() {
+            assert!(tree.contains(p.clone()));
+            assert!(balanced.contains(p.clone()));
+        }
+        let q = Point2D::new(0.4, 0.3);
+        let mut set = PointSET::new();
+        line.into_iter().for_each(|p| set.insert(p));
+        assert_eq!(balanced.nearest_k(q.clone(), 3), set.nearest_k(q, 3));
+    }
+
+    #[test]
+    fn test_bounds() {
+        // longitude and latitude, far outside the unit square
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut tree = KdTree::new(SplitHV::V);
+        let mut set = PointSET::new();
+        assert_eq!(tree.bounds(), None);
+        for _ in 0..2000 {
+            let p = Point2D::new(rng.gen_range(-180.0..180.0), rng.gen_range(-90.0..90.0));
+            tree.insert(p.clone());
+            set.insert(p);
+        }
+        let b = tree.bounds().unwrap();
+        assert!(set.all().iter().all(|p| b.contains(p)));
+        let balanced = KdTree::from_points(set.all().into_iter().cloned().collect());
+        assert_eq!(balanced.bounds(), tree.bounds());
+
+        for t in [&tree, &balanced] {
+            for _ in 0..20 {
+                let q = Point2D::new(rng.gen_range(-200.0..200.0), rng.gen_range(-100.0..100.0));
+                assert_eq!(t.nearest(q.clone()), set.nearest_k(q.clone(), 1).pop());
+                assert_eq!(t.nearest_k(q.clone(), 5), set.nearest_k(q.clone(), 5));
+                let circle = Circle::new(q, 30.0);
+                let mut got = t.range(&circle);
+                got.sort();
+                assert_eq!(got, set.range(&circle));
             }
         }
     }
+
+    #[test]
+    fn test_nearest_approx() {
+        let mut rng = StdRng::seed_from_u64(10);
+        let tree = KdTree::from_points((0..20_000).map(|_| random_point(&mut rng)).collect());
+        assert_eq!(
+            KdTree::new(SplitHV::V).nearest_approx(Point2D::new(0.5, 0.5), 0.1),
+            None
+        );
+        let queries: Vec<Point2D> = (0..3000).map(|_| random_point(&mut rng)).collect();
+        let exact = tree.nearest_many(&queries);
+        let (mut visited_exact, mut visited_approx) = (0, 0);
+        for (q, e) in queries.iter().zip(exact.iter()) {
+            assert_eq!(*e, tree.nearest(q.clone()));
+            assert_eq!(tree.nearest_approx(q.clone(), 0.0), *e);
+            let best = q.distance_to(e.unwrap());
+            let (a, stats) = tree.nearest_approx_with_stats(q.clone(), 0.5);
+            assert!(q.distance_to(a.unwrap()) <= 1.5 * best);
+            visited_approx += stats.nodes_visited;
+            visited_exact += tree.nearest_with_stats(q.clone()).1.nodes_visited;
+        }
+        assert!(visited_approx < visited_exact);
+
+        let approx = tree.nearest_approx_many(&queries, 0.5);
+        assert_eq!(approx.len(), queries.len());
+        for (q, a) in queries.iter().zip(approx) {
+            assert_eq!(a, tree.nearest_approx(q.clone(), 0.5));
+        }
+        assert!(tree.nearest_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut tree = KdTree::new(SplitHV::V);
+        let mut set = PointSET::new();
+        for _ in 0..2000 {
+            let p = random_point(&mut rng);
+            tree.insert(p.clone());
+            set.insert(p);
+        }
+        let check = |region: &dyn Region| {
+            let mut expected = set.range(region);
+            expected.sort();
+            let mut got = tree.range(region);
+            got.sort();
+            assert_eq!(got, expected);
+            assert_eq!(tree.range_iter(region).count(), expected.len());
+            assert_eq!(set.range_iter(region).count(), expected.len());
+        };
+        check(&RectHV::new(0.25, 0.25, 0.75, 0.75));
+        check(&RectHV::new(0.9, 0.0, 1.0, 0.05));
+        check(&Circle::new(Point2D::new(0.3, 0.6), 0.2));
+        check(&Circle::new(Point2D::new(1.0, 1.0), 0.1));
+        check(&ConvexPolygon::new(vec![
+            Point2D::new(0.1, 0.1),
+            Point2D::new(0.9, 0.2),
+            Point2D::new(0.5, 0.8),
+        ]));
+    }
 }
@@ -0,0 +1,368 @@
+// A kd-tree over K-dimensional points [f64; K], with the same pruning as the 2D
+// KdTree. The split axis cycles 0, 1, ..., K-1 with depth, and the nodes live in
+// an arena the same way.
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+// index of a missing child
+const NIL: u32 = u32::MAX;
+
+/// The axis aligned box [min[0], max[0]] x ... x [min[K-1], max[K-1]].
+#[derive(Debug, PartialEq, Clone)]
+pub struct HyperRect<const K: usize> {
+    min: [f64; K],
+    max: [f64; K],
+}
+
+impl<const K: usize> HyperRect<K> {
+    pub fn new(min: [f64; K], max: [f64; K]) -> Self {
+        assert!((0..K).all(|d| min[d] <= max[d]), "min must not exceed max");
+        Self { min, max }
+    }
+
+    // the box holding just the point p
+    fn point(p: &[f64; K]) -> Self {
+        Self { min: *p, max: *p }
+    }
+
+    // grow the box to take in the point p
+    fn extend(&mut self, p: &[f64; K]) {
+        for ((lo, hi), x) in self.min.iter_mut().zip(self.max.iter_mut()).zip(p) {
+            *lo = lo.min(*x);
+            *hi = hi.max(*x);
+        }
+    }
+
+    pub fn min(&self) -> &[f64; K] {
+        &self.min
+    }
+
+    pub fn max(&self) -> &[f64; K] {
+        &self.max
+    }
+
+    // does the box contain the point p (either inside or on boundary)?
+    pub fn contains(&self, p: &[f64; K]) -> bool {
+        (0..K).all(|d| self.min[d] <= p[d] && p[d] <= self.max[d])
+    }
+
+    // does this box intersect that box (at one or more points)?
+    pub fn intersects(&self, other: &HyperRect<K>) -> bool {
+        (0..K).all(|d| self.min[d] <= other.max[d] && other.min[d] <= self.max[d])
+    }
+
+    // square of Euclidean distance from point p to closest point in the box
+    pub fn distance_squared_to(&self, p: &[f64; K]) -> f64 {
+        (0..K)
+            .map(|d| {
+                let delta = if p[d] < self.min[d] {
+                    self.min[d] - p[d]
+                } else if p[d] > self.max[d] {
+                    p[d] - self.max[d]
+                } else {
+                    0.0
+                };
+                delta * delta
+            })
+            .sum()
+    }
+
+    // the boxes either side of a split at value along axis
+    fn split(&self, axis: usize, value: f64) -> (Self, Self) {
+        let mut left = self.clone();
+        let mut right = self.clone();
+        left.max[axis] = value;
+        right.min[axis] = value;
+        (left, right)
+    }
+}
+
+fn distance_squared<const K: usize>(a: &[f64; K], b: &[f64; K]) -> f64 {
+    (0..K).map(|d| (a[d] - b[d]) * (a[d] - b[d])).sum()
+}
+
+// a point at some distance from the query, ordered by distance and then by point
+struct Candidate<'a, const K: usize> {
+    distance: f64,
+    point: &'a [f64; K],
+}
+
+impl<const K: usize> Ord for Candidate<'_, K> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then_with(|| {
+            (0..K)
+                .map(|d| self.point[d].total_cmp(&other.point[d]))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+}
+
+impl<const K: usize> PartialOrd for Candidate<'_, K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const K: usize> PartialEq for Candidate<'_, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl<const K: usize> Eq for Candidate<'_, K> {}
+
+struct Node<const K: usize> {
+    point: [f64; K],
+    left: u32,
+    right: u32,
+}
+
+pub struct KdTreeN<const K: usize> {
+    nodes: Vec<Node<K>>,
+    root: u32,
+    // smallest box holding every point, the root's box when searching
+    bounds: Option<HyperRect<K>>,
+}
+
+impl<const K: usize> Default for KdTreeN<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const K: usize> KdTreeN<K> {
+    // construct an empty tree of points
+    pub fn new() -> Self {
+        assert!(K > 0, "points need at least one dimension");
+        Self {
+            nodes: vec![],
+            root: NIL,
+            bounds: None,
+        }
+    }
+
+    // is the tree empty?
+    pub fn is_empty(&self) -> bool {
+        self.root == NIL
+    }
+
+    // number of points in the tree
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn node(&self, i: u32) -> &Node<K> {
+        &self.nodes[i as usize]
+    }
+
+    // add the point to the tree; points equal along the split axis go right
+    pub fn insert(&mut self, p: [f64; K]) {
+        let mut parent = NIL;
+        let mut left = false;
+        let mut i = self.root;
+        let mut axis = 0;
+        while i != NIL {
+            let node = self.node(i);
+            left = p[axis] < node.point[axis];
+            parent = i;
+            i = if left { node.left } else { node.right };
+            axis = (axis + 1) % K;
+        }
+        self.bounds = Some(match self.bounds.take() {
+            Some(mut b) => {
+                b.extend(&p);
+                b
+            }
+            None => HyperRect::point(&p),
+        });
+        assert!(self.nodes.len() < NIL as usize, "too many points");
+        let i = self.nodes.len() as u32;
+        self.nodes.push(Node {
+            point: p,
+            left: NIL,
+            right: NIL,
+        });
+        if parent == NIL {
+            self.root = i;
+        } else if left {
+            self.nodes[parent as usize].left = i;
+        } else {
+            self.nodes[parent as usize].right = i;
+        }
+    }
+
+    // does the tree contain point p?
+    pub fn contains(&self, p: &[f64; K]) -> bool {
+        let mut i = self.root;
+        let mut axis = 0;
+        while i != NIL {
+            let node = self.node(i);
+            if node.point == *p {
+                return true;
+            }
+            i = if p[axis] < node.point[axis] {
+                node.left
+            } else {
+                node.right
+            };
+            axis = (axis + 1) % K;
+        }
+        false
+    }
+
+    // the root with its axis and box, where every search starts
+    fn root_entry(&self) -> Option<(u32, usize, HyperRect<K>)> {
+        let bounds = self.bounds.clone()?;
+        (self.root != NIL).then_some((self.root, 0, bounds))
+    }
+
+    // all points that are inside the box (or on the boundary)
+    pub fn range(&self, rect: &HyperRect<K>) -> Vec<&[f64; K]> {
+        let mut points = vec![];
+        let mut stack: Vec<_> = self.root_entry().into_iter().collect();
+        while let Some((i, axis, node_rect)) = stack.pop() {
+            let node = self.node(i);
+            if rect.contains(&node.point) {
+                points.push(&node.point);
+            }
+            let (left_rect, right_rect) = node_rect.split(axis, node.point[axis]);
+            for (child, child_rect) in [(node.right, right_rect), (node.left, left_rect)] {
+                if child != NIL && rect.intersects(&child_rect) {
+                    stack.push((child, (axis + 1) % K, child_rect));
+                }
+            }
+        }
+        points
+    }
+
+    // a nearest neighbor in the tree to point p; None if the tree is empty
+    pub fn nearest(&self, p: &[f64; K]) -> Option<&[f64; K]> {
+        self.nearest_k(p, 1).pop()
+    }
+
+    // the k points in the tree closest to p, nearest first (ties go to the
+    // lexicographically smaller point)
+    pub fn nearest_k(&self, p: &[f64; K], k: usize) -> Vec<&[f64; K]> {
+        /* Keep the best k so far in a max-heap. Search the side of each split the
+        query point is on first, and skip a box once the heap is full and the box is
+        further away than the worst point in it. */
+        let mut heap: BinaryHeap<Candidate<K>> = BinaryHeap::with_capacity(k + 1);
+        let mut stack = vec![];
+        if k > 0 {
+            stack.extend(self.root_entry());
+        }
+        // <= so that an equally distant but smaller point can still get in
+        let worth_visiting = |heap: &BinaryHeap<Candidate<K>>, d: f64| {
+            heap.len() < k || heap.peek().is_some_and(|worst| d <= worst.distance)
+        };
+        while let Some((i, axis, rect)) = stack.pop() {
+            if !worth_visiting(&heap, rect.distance_squared_to(p)) {
+                continue;
+            }
+            let node = self.node(i);
+            let candidate = Candidate {
+                distance: distance_squared(&node.point, p),
+                point: &node.point,
+            };
+            if heap.len() < k {
+                heap.push(candidate);
+            } else if heap.peek().is_some_and(|worst| candidate < *worst) {
+                heap.pop();
+                heap.push(candidate);
+            }
+            let (left_rect, right_rect) = rect.split(axis, node.point[axis]);
+            // push the query point's side last so it comes off the stack first
+            let children = if p[axis] < node.point[axis] {
+                [(node.right, right_rect), (node.left, left_rect)]
+            } else {
+                [(node.left, left_rect), (node.right, right_rect)]
+            };
+            for (child, child_rect) in children {
+                if child != NIL && worth_visiting(&heap, child_rect.distance_squared_to(p)) {
+                    stack.push((child, (axis + 1) % K, child_rect));
+                }
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|c| c.point)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn check<const K: usize>(seed: u64, n: usize, grid: bool) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let random_point = |rng: &mut StdRng| -> [f64; K] {
+            std::array::from_fn(|_| {
+                if grid {
+                    // lots of ties along every axis
+                    rng.gen_range(0..4) as f64
+                } else {
+                    rng.gen_range(-10.0..10.0)
+                }
+            })
+        };
+        let mut tree = KdTreeN::<K>::new();
+        assert!(tree.nearest(&[0.0; K]).is_none());
+        let mut points = vec![];
+        for _ in 0..n {
+            let p = random_point(&mut rng);
+            tree.insert(p);
+            points.push(p);
+        }
+        assert_eq!(tree.size(), n);
+        for p in points.iter() {
+            assert!(tree.contains(p));
+        }
+        for _ in 0..50 {
+            let q = random_point(&mut rng);
+            let mut expected: Vec<Candidate<K>> = points
+                .iter()
+                .map(|p| Candidate {
+                    distance: distance_squared(p, &q),
+                    point: p,
+                })
+                .collect();
+            expected.sort();
+            let expected: Vec<&[f64; K]> = expected.into_iter().take(7).map(|c| c.point).collect();
+            assert_eq!(tree.nearest_k(&q, 7), expected);
+            assert_eq!(tree.nearest(&q), Some(expected[0]));
+
+            let r = random_point(&mut rng);
+            let rect = HyperRect::new(
+                std::array::from_fn(|d| q[d].min(r[d])),
+                std::array::from_fn(|d| q[d].max(r[d])),
+            );
+            let mut got = tree.range(&rect);
+            let mut expected: Vec<&[f64; K]> = points.iter().filter(|p| rect.contains(p)).collect();
+            got.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(got, expected);
+        }
+    }
+
+    #[test]
+    fn test() {
+        check::<1>(1, 500, false);
+        check::<2>(2, 1000, false);
+        check::<3>(3, 1000, false);
+        check::<3>(4, 500, true);
+        check::<8>(5, 2000, false);
+    }
+
+    #[test]
+    fn test_hyper_rect() {
+        let r = HyperRect::new([0.0, 0.0, 0.0], [1.0, 2.0, 3.0]);
+        assert!(r.contains(&[1.0, 2.0, 3.0]));
+        assert!(!r.contains(&[1.0, 2.0, 3.5]));
+        assert_eq!(r.distance_squared_to(&[2.0, -1.0, 1.0]), 2.0);
+        assert!(r.intersects(&HyperRect::new([1.0, 2.0, 3.0], [4.0, 4.0, 4.0])));
+        assert!(!r.intersects(&HyperRect::new([0.5, 0.5, 3.1], [4.0, 4.0, 4.0])));
+    }
+}
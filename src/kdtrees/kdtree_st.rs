@@ -0,0 +1,913 @@
+use std::{
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet},
+    io::{self, Read, Write},
+    thread,
+};
+
+use float_ord::FloatOrd;
+use plotters::style::BLUE;
+
+use crate::{
+    error::AlgoError,
+    util::{point2d::Point2D, rect_hv::RectHV, region::Region, std_draw::Plot},
+};
+
+use super::{
+    kdtree::SplitHV,
+    persist::{Decoder, Encoder},
+    query_stats::{QueryObserver, QueryStats},
+};
+
+// index of a missing child
+const NIL: u32 = u32::MAX;
+
+struct Node<V> {
+    point: Point2D,
+    value: V,
+    left: u32,
+    right: u32,
+}
+
+/* A symbol table from points to values on a 2d-tree. The nodes live in one Vec and
+point at their children by index, so a tree is a couple of allocations instead of one
+per point, and every operation walks it with a loop or an explicit stack instead of
+recursing. Split directions aren't stored, they alternate level by level starting
+from the root's. Removing a node moves the last one into its slot, so the Vec
+always holds exactly the entries. */
+pub struct KdTreeST<V> {
+    split: SplitHV,
+    nodes: Vec<Node<V>>,
+    root: u32,
+    // rectangle of the root, see bounds
+    bounds: Option<RectHV>,
+}
+
+impl<V> Default for KdTreeST<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> KdTreeST<V> {
+    // construct an empty symbol table of points
+    pub fn new() -> Self {
+        Self::with_split(SplitHV::V)
+    }
+
+    // construct an empty symbol table whose root splits in the given direction
+    pub fn with_split(split: SplitHV) -> Self {
+        Self {
+            split,
+            nodes: vec![],
+            root: NIL,
+            bounds: None,
+        }
+    }
+
+    // perfectly balanced tree of the entries; of entries with the same point, the last
+    // one's value is kept, as if they had been put one by one
+    pub(crate) fn balanced(split: SplitHV, entries: Vec<(Point2D, V)>) -> Self {
+        // copies of a point tie on every coordinate, so each would go right of the
+        // last and n of them would make a chain n deep
+        let mut slots: HashMap<Point2D, usize> = HashMap::with_capacity(entries.len());
+        let mut kept: Vec<(Point2D, V)> = Vec::with_capacity(entries.len());
+        for (p, v) in entries {
+            match slots.entry(p) {
+                Entry::Occupied(e) => kept[*e.get()].1 = v,
+                Entry::Vacant(e) => {
+                    kept.push((e.key().clone(), v));
+                    e.insert(kept.len() - 1);
+                }
+            }
+        }
+        let mut entries = kept;
+        let mut t = Self::with_split(split);
+        entries.iter().for_each(|(p, _)| t.extend_bounds(p));
+        assert!(entries.len() < NIL as usize, "too many points");
+        let mut links = vec![(NIL, NIL); entries.len()];
+        t.root = Self::build(split, &mut entries, &mut links);
+        t.nodes = entries
+            .into_iter()
+            .zip(links)
+            .map(|((point, value), (left, right))| Node {
+                point,
+                value,
+                left,
+                right,
+            })
+            .collect();
+        t
+    }
+
+    fn build(split: SplitHV, entries: &mut [(Point2D, V)], links: &mut [(u32, u32)]) -> u32 {
+        /* Bulk loading. Split the points at the median along this node's direction, found
+        with a linear time selection rather than a sort, and build each half the same way
+        with the other direction. Points with the same coordinate as the split have to go
+        right (as insert sends them), so the node takes the first of any points that tie
+        on the median and everything smaller stays on the left. Each half is only ever
+        rearranged within itself, so an entry's final position is its node's index.
+        The halves wait on a stack with the link to set once their node is known. */
+        let mut root = NIL;
+        let mut stack = vec![(0, entries.len(), split, NIL, false)];
+        while let Some((lo, hi, split, parent, left)) = stack.pop() {
+            if lo == hi {
+                continue;
+            }
+            let part = &mut entries[lo..hi];
+            let mid = part.len() / 2;
+            part.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+                split.coord(a).total_cmp(&split.coord(b))
+            });
+            let median = split.coord(&part[mid].0);
+            let mut lt = 0;
+            for i in 0..mid {
+                if split.coord(&part[i].0) < median {
+                    part.swap(i, lt);
+                    lt += 1;
+                }
+            }
+            let i = lo + lt;
+            match (parent, left) {
+                (NIL, _) => root = i as u32,
+                (_, true) => links[parent as usize].0 = i as u32,
+                (_, false) => links[parent as usize].1 = i as u32,
+            }
+            stack.push((lo, i, split.flip(), i as u32, true));
+            stack.push((i + 1, hi, split.flip(), i as u32, false));
+        }
+        root
+    }
+
+    // rebuild the tree so that it is perfectly balanced
+    pub fn rebalance(&mut self) {
+        let entries = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(|n| (n.point, n.value))
+            .collect();
+        *self = Self::balanced(self.split, entries);
+    }
+
+    fn node(&self, i: u32) -> &Node<V> {
+        &self.nodes[i as usize]
+    }
+
+    // point the parent's left or right child at child (a NIL parent means the root)
+    fn set_child(&mut self, parent: u32, left: bool, child: u32) {
+        if parent == NIL {
+            self.root = child;
+        } else if left {
+            self.nodes[parent as usize].left = child;
+        } else {
+            self.nodes[parent as usize].right = child;
+        }
+    }
+
+    // number of nodes on the longest path from the root to a leaf (0 for an empty tree)
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut stack = vec![];
+        if self.root != NIL {
+            stack.push((self.root, 1));
+        }
+        while let Some((i, depth)) = stack.pop() {
+            height = height.max(depth);
+            let node = self.node(i);
+            for child in [node.left, node.right] {
+                if child != NIL {
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        height
+    }
+
+    // is the symbol table empty?
+    pub fn is_empty(&self) -> bool {
+        self.root == NIL
+    }
+
+    // number of points in the symbol table
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // the smallest rectangle containing every point put so far (removing points
+    // doesn't shrink it); None until the first put
+    pub fn bounds(&self) -> Option<&RectHV> {
+        self.bounds.as_ref()
+    }
+
+    fn extend_bounds(&mut self, p: &Point2D) {
+        /* The pruning in range and nearest relies on every point of a subtree lying
+        inside its rectangle, so the root's rectangle can't be a fixed unit square:
+        grow it to take in each new point. Then any coordinates work. */
+        self.bounds = Some(match &self.bounds {
+            Some(b) => RectHV::new(
+                b.xmin().min(p.x()),
+                b.ymin().min(p.y()),
+                b.xmax().max(p.x()),
+                b.ymax().max(p.y()),
+            ),
+            None => RectHV::new(p.x(), p.y(), p.x(), p.y()),
+        });
+    }
+
+    // the root with its split and rectangle, where every search starts
+    fn root_entry(&self) -> Option<(u32, SplitHV, RectHV)> {
+        let bounds = self.bounds.clone()?;
+        (self.root != NIL).then_some((self.root, self.split, bounds))
+    }
+
+    // walk down towards p; returns the node holding p (or NIL) with its parent,
+    // which side of the parent it is on and its split
+    fn find(&self, p: &Point2D) -> (u32, bool, u32, SplitHV) {
+        let (mut parent, mut left) = (NIL, false);
+        let mut i = self.root;
+        let mut split = self.split;
+        while i != NIL && self.node(i).point != *p {
+            let node = self.node(i);
+            left = split.coord(p) < split.coord(&node.point);
+            parent = i;
+            i = if left { node.left } else { node.right };
+            split = split.flip();
+        }
+        (parent, left, i, split)
+    }
+
+    // add a node for p below the last node on its path, even if p is already there
+    pub(super) fn insert(&mut self, p: Point2D, value: V) {
+        let (mut parent, mut left) = (NIL, false);
+        let mut i = self.root;
+        let mut split = self.split;
+        while i != NIL {
+            let node = self.node(i);
+            left = split.coord(&p) < split.coord(&node.point);
+            parent = i;
+            i = if left { node.left } else { node.right };
+            split = split.flip();
+        }
+        self.extend_bounds(&p);
+        assert!(self.nodes.len() < NIL as usize, "too many points");
+        self.nodes.push(Node {
+            point: p,
+            value,
+            left: NIL,
+            right: NIL,
+        });
+        self.set_child(parent, left, (self.nodes.len() - 1) as u32);
+    }
+
+    // associate the value with point p, replacing any value p already had
+    pub fn put(&mut self, p: Point2D, value: V) {
+        match self.find(&p) {
+            (_, _, NIL, _) => self.insert(p, value),
+            (_, _, i, _) => self.nodes[i as usize].value = value,
+        }
+    }
+
+    // value associated with point p
+    pub fn get(&self, p: Point2D) -> Option<&V> {
+        match self.find(&p) {
+            (_, _, NIL, _) => None,
+            (_, _, i, _) => Some(&self.node(i).value),
+        }
+    }
+
+    // does the symbol table contain point p?
+    pub fn contains(&self, p: Point2D) -> bool {
+        self.find(&p).2 != NIL
+    }
+
+    // contains, reporting the nodes on the path to the observer
+    pub fn contains_observed<O: QueryObserver>(&self, p: Point2D, mut observer: O) -> bool {
+        let mut entry = self.root_entry();
+        while let Some((i, split, rect)) = entry {
+            let node = self.node(i);
+            observer.visit(&node.point, split, &rect);
+            if node.point == p {
+                return true;
+            }
+            let (left_rect, right_rect) = split.split_rect(&node.point, &rect);
+            let (next, skipped) = if split.coord(&p) < split.coord(&node.point) {
+                ((node.left, left_rect), (node.right, right_rect))
+            } else {
+                ((node.right, right_rect), (node.left, left_rect))
+            };
+            if skipped.0 != NIL {
+                observer.prune(&skipped.1);
+            }
+            entry = (next.0 != NIL).then_some((next.0, split.flip(), next.1));
+        }
+        false
+    }
+
+    // contains, with the cost of the search
+    pub fn contains_with_stats(&self, p: Point2D) -> (bool, QueryStats) {
+        QueryStats::measure(|stats| self.contains_observed(p, stats))
+    }
+
+    // remove point p and return its value; None if it wasn't there
+    pub fn remove(&mut self, p: Point2D) -> Option<V> {
+        /* Deletion. Find the node holding p. A leaf can simply be dropped, otherwise its
+        point is replaced by the point in its right subtree with the smallest coordinate
+        along the node's split, which is then deleted from that subtree in turn.
+        Everything left in the right subtree is still >= the replacement. If there is
+        only a left subtree, take its minimum instead and move it to the right: the
+        left side must hold strictly smaller points, but the right side may hold equal ones. */
+        match self.find(&p) {
+            (_, _, NIL, _) => None,
+            (parent, left, i, split) => Some(self.remove_node(parent, left, i, split)),
+        }
+    }
+
+    // delete node i, split by split, which hangs off the left or right of parent
+    fn remove_node(
+        &mut self,
+        mut parent: u32,
+        mut left: bool,
+        mut i: u32,
+        mut split: SplitHV,
+    ) -> V {
+        loop {
+            let node = &mut self.nodes[i as usize];
+            if node.right == NIL {
+                if node.left == NIL {
+                    self.set_child(parent, left, NIL);
+                    return self.swap_remove(i).value;
+                }
+                node.right = node.left;
+                node.left = NIL;
+            }
+            // swap in the entry of the right subtree's minimum, then go and delete that node
+            let right = node.right;
+            let min = self.find_min(i, right, split.flip(), split);
+            let (a, b) = (i.min(min.2) as usize, i.max(min.2) as usize);
+            let (low, high) = self.nodes.split_at_mut(b);
+            std::mem::swap(&mut low[a].point, &mut high[0].point);
+            std::mem::swap(&mut low[a].value, &mut high[0].value);
+            (parent, left, i, split) = min;
+        }
+    }
+
+    // take the unlinked node i out of the Vec, moving the last node into its slot
+    fn swap_remove(&mut self, i: u32) -> Node<V> {
+        let removed = self.nodes.swap_remove(i as usize);
+        let moved = self.nodes.len() as u32;
+        if i != moved {
+            // the moved node's path follows its point, find its parent that way
+            if self.root == moved {
+                self.root = i;
+            } else {
+                let p = &self.node(i).point;
+                let mut j = self.root;
+                let mut split = self.split;
+                loop {
+                    let node = self.node(j);
+                    let left = split.coord(p) < split.coord(&node.point);
+                    let child = if left { node.left } else { node.right };
+                    if child == moved {
+                        self.set_child(j, left, i);
+                        break;
+                    }
+                    j = child;
+                    split = split.flip();
+                }
+            }
+        }
+        removed
+    }
+
+    // the node with the smallest x (V) or y (H) coordinate in the subtree at i, which
+    // is the right child of parent, as (parent, is left child, node, split)
+    fn find_min(
+        &self,
+        parent: u32,
+        i: u32,
+        split: SplitHV,
+        dim: SplitHV,
+    ) -> (u32, bool, u32, SplitHV) {
+        let mut best = (parent, false, i, split);
+        let mut stack = vec![best];
+        while let Some(entry) = stack.pop() {
+            let (_, _, j, s) = entry;
+            let node = self.node(j);
+            if dim.coord(&node.point) < dim.coord(&self.node(best.2).point) {
+                best = entry;
+            }
+            if node.left != NIL {
+                stack.push((j, true, node.left, s.flip()));
+            }
+            // when split the same way, everything on the right is >= this node
+            if s != dim && node.right != NIL {
+                stack.push((j, false, node.right, s.flip()));
+            }
+        }
+        best
+    }
+
+    // draw all points to standard draw
+    pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        for node in self.nodes.iter() {
+            node.point.draw(plot)?;
+        }
+        Ok(())
+    }
+
+    // draw all points with the split line of each node across the node's rectangle
+    pub fn draw_splits(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        let mut stack: Vec<_> = self.root_entry().into_iter().collect();
+        while let Some((i, split, rect)) = stack.pop() {
+            let node = self.node(i);
+            split.draw(&node.point, &rect, plot)?;
+            let (left_rect, right_rect) = split.split_rect(&node.point, &rect);
+            for (child, child_rect) in [(node.left, left_rect), (node.right, right_rect)] {
+                if child != NIL {
+                    stack.push((child, split.flip(), child_rect));
+                }
+            }
+        }
+        plot.set_pen_color(BLUE);
+        // points last so the lines don't cover them
+        self.draw(plot)
+    }
+
+    // all entries whose points are inside the region (or on the boundary)
+    pub fn range<R: Region>(&self, region: R) -> Vec<(&Point2D, &V)> {
+        /* Range search. To find all points contained in a given query rectangle,
+        start at the root and recursively search for points in both subtrees using the following pruning rule:
+        if the query rectangle does not intersect the rectangle corresponding to a node,
+        there is no need to explore that node (or its subtrees).
+        A subtree is searched only if it might contain a point contained in the query rectangle. */
+        self.range_iter(region).collect()
+    }
+
+    // lazily iterate over the entries inside the region, same pruning as range
+    pub fn range_iter<R: Region>(&self, region: R) -> RangeIter<'_, V, R> {
+        self.range_iter_observed(region, ())
+    }
+
+    // range_iter, reporting what the search does to the observer
+    pub fn range_iter_observed<R: Region, O: QueryObserver>(
+        &self,
+        region: R,
+        observer: O,
+    ) -> RangeIter<'_, V, R, O> {
+        RangeIter {
+            tree: self,
+            region,
+            observer,
+            stack: self.root_entry().into_iter().collect(),
+        }
+    }
+
+    // range, with the cost of the search
+    pub fn range_with_stats<R: Region>(&self, region: R) -> (Vec<(&Point2D, &V)>, QueryStats) {
+        QueryStats::measure(|stats| self.range_iter_observed(region, stats).collect())
+    }
+
+    // the nearest neighbor of point p and its value (of those equally near, the
+    // smallest point); None if the symbol table is empty
+    pub fn nearest(&self, p: Point2D) -> Option<(&Point2D, &V)> {
+        self.nearest_observed(p, ())
+    }
+
+    // nearest, with the cost of the search
+    pub fn nearest_with_stats(&self, p: Point2D) -> (Option<(&Point2D, &V)>, QueryStats) {
+        QueryStats::measure(|stats| self.nearest_observed(p, stats))
+    }
+
+    // nearest, reporting what the search does to the observer
+    pub fn nearest_observed<O: QueryObserver>(
+        &self,
+        p: Point2D,
+        observer: O,
+    ) -> Option<(&Point2D, &V)> {
+        /* Nearest-neighbor search. To find a closest point to a given query point,
+        start at the root and recursively search in both subtrees using the following pruning rule:
+        if the closest point discovered so far is closer than the distance between the query point and the rectangle
+        corresponding to a node, there is no need to explore that node (or its subtrees).
+        That is, search a node only only if it might contain a point that is closer than the best one found so far.
+        The effectiveness of the pruning rule depends on quickly finding a nearby point.
+        To do this, organize the recursive method so that when there are two possible subtrees to go down,
+        you always choose the subtree that is on the same side of the splitting line as the query point as the
+        first subtree to explore—the closest point found while exploring the first subtree may enable pruning of
+        the second subtree. */
+        self.search_nearest(p, 0.0, observer)
+    }
+
+    // a point within a factor (1 + eps) of the distance to the nearest neighbor of
+    // p, and its value; None if the symbol table is empty
+    pub fn nearest_approx(&self, p: Point2D, eps: f64) -> Option<(&Point2D, &V)> {
+        /* Approximate nearest neighbor. Prune a rectangle as soon as it is further than
+        best / (1 + eps): anything inside it could only improve on the best so far by
+        that factor. The bigger eps, the less of the tree is searched. */
+        assert!(eps >= 0.0, "eps must not be negative");
+        self.search_nearest(p, eps, ())
+    }
+
+    // nearest_approx, with the cost of the search
+    pub fn nearest_approx_with_stats(
+        &self,
+        p: Point2D,
+        eps: f64,
+    ) -> (Option<(&Point2D, &V)>, QueryStats) {
+        assert!(eps >= 0.0, "eps must not be negative");
+        QueryStats::measure(|stats| self.search_nearest(p, eps, stats))
+    }
+
+    fn search_nearest<O: QueryObserver>(
+        &self,
+        p: Point2D,
+        eps: f64,
+        mut observer: O,
+    ) -> Option<(&Point2D, &V)> {
+        // distances are squared, so the factor is too
+        let factor = (1.0 + eps) * (1.0 + eps);
+        let root = self.root_entry()?;
+        let mut best = self.root;
+        let mut best_distance = f64::INFINITY;
+        // the stack stands in for the recursion: a subtree is checked against the
+        // best distance when it is popped, after everything pushed above it. Equally
+        // distant subtrees are still searched, so that ties go to the smaller point.
+        let mut stack = vec![root];
+        while let Some((i, split, rect)) = stack.pop() {
+            if rect.distance_squared_to(&p) * factor > best_distance {
+                observer.prune(&rect);
+                continue;
+            }
+            let node = self.node(i);
+            observer.visit(&node.point, split, &rect);
+            observer.distance();
+            let d = node.point.distance_squared_to(&p);
+            if d < best_distance || (d == best_distance && node.point < self.node(best).point) {
+                best = i;
+                best_distance = d;
+            }
+            self.push_children(&mut stack, &p, i, split, &rect, &mut observer, |d| {
+                d * factor <= best_distance
+            });
+        }
+        let node = self.node(best);
+        Some((&node.point, &node.value))
+    }
+
+    // push the children of node i that keep accepts the distance to, the one on the
+    // query point's side of the split last
+    #[allow(clippy::too_many_arguments)]
+    fn push_children(
+        &self,
+        stack: &mut Vec<(u32, SplitHV, RectHV)>,
+        query_point: &Point2D,
+        i: u32,
+        split: SplitHV,
+        rect: &RectHV,
+        observer: &mut impl QueryObserver,
+        keep: impl Fn(f64) -> bool,
+    ) {
+        let node = self.node(i);
+        let (left_rect, right_rect) = split.split_rect(&node.point, rect);
+        let children = if split.coord(query_point) < split.coord(&node.point) {
+            [(node.right, right_rect), (node.left, left_rect)]
+        } else {
+            [(node.left, left_rect), (node.right, right_rect)]
+        };
+        for (child, child_rect) in children {
+            if child == NIL {
+                continue;
+            }
+            if keep(child_rect.distance_squared_to(query_point)) {
+                stack.push((child, split.flip(), child_rect));
+            } else {
+                observer.prune(&child_rect);
+            }
+        }
+    }
+
+    // the k entries closest to p, nearest first (ties go to the smaller point)
+    pub fn nearest_k(&self, p: Point2D, k: usize) -> Vec<(&Point2D, &V)> {
+        /* Same search as nearest, but the best point so far becomes a bounded max-heap of
+        the best k. A node's rectangle can be pruned once the heap is full and the
+        rectangle is further away than the worst point in the heap. */
+        let mut heap: BinaryHeap<(FloatOrd<f64>, &Point2D, u32)> = BinaryHeap::with_capacity(k + 1);
+        let mut stack = vec![];
+        if k > 0 {
+            stack.extend(self.root_entry());
+        }
+        while let Some((i, split, rect)) = stack.pop() {
+            let d = FloatOrd(rect.distance_squared_to(&p));
+            // <= so that an equally distant but smaller point can still get in
+            if heap.len() == k && heap.peek().is_some_and(|worst| d > worst.0) {
+                continue;
+            }
+            let point = &self.node(i).point;
+            let candidate = (FloatOrd(point.distance_squared_to(&p)), point, i);
+            if heap.len() < k {
+                heap.push(candidate);
+            } else if heap.peek().is_some_and(|worst| candidate < *worst) {
+                heap.pop();
+                heap.push(candidate);
+            }
+            let worst = match heap.peek() {
+                Some(worst) if heap.len() == k => worst.0 .0,
+                _ => f64::INFINITY,
+            };
+            self.push_children(&mut stack, &p, i, split, &rect, &mut (), |d| d <= worst);
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|(_, p, i)| (p, &self.node(i).value))
+            .collect()
+    }
+
+    // every entry, in no particular order
+    pub fn all(&self) -> Vec<(&Point2D, &V)> {
+        self.nodes.iter().map(|n| (&n.point, &n.value)).collect()
+    }
+}
+
+impl<V: Sync> KdTreeST<V> {
+    // nearest for every query point, spreading the queries over all CPUs
+    pub fn nearest_many(&self, queries: &[Point2D]) -> Vec<Option<(&Point2D, &V)>> {
+        batch(queries, cpus(), |q| self.nearest(q.clone()))
+    }
+
+    // nearest_approx for every query point, spreading the queries over all CPUs
+    pub fn nearest_approx_many(
+        &self,
+        queries: &[Point2D],
+        eps: f64,
+    ) -> Vec<Option<(&Point2D, &V)>> {
+        assert!(eps >= 0.0, "eps must not be negative");
+        batch(queries, cpus(), |q| self.search_nearest(q.clone(), eps, ()))
+    }
+}
+
+// the body of a saved KdTree, see persist
+impl KdTreeST<()> {
+    // split, node count, root, bounds, then every node as its point and children
+    pub(super) fn encode<W: Write>(&self, enc: &mut Encoder<W>) -> io::Result<()> {
+        enc.u8(match self.split {
+            SplitHV::H => b'H',
+            SplitHV::V => b'V',
+        })?;
+        enc.u64(self.nodes.len() as u64)?;
+        enc.u32(self.root)?;
+        match &self.bounds {
+            Some(b) => {
+                enc.u8(1)?;
+                for x in [b.xmin(), b.ymin(), b.xmax(), b.ymax()] {
+                    enc.f64(x)?;
+                }
+            }
+            None => enc.u8(0)?,
+        }
+        for node in self.nodes.iter() {
+            enc.point(&node.point)?;
+            enc.u32(node.left)?;
+            enc.u32(node.right)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn decode<R: Read>(mut dec: Decoder<R>) -> Result<Self, AlgoError> {
+        let split = match dec.u8()? {
+            b'H' => SplitHV::H,
+            b'V' => SplitHV::V,
+            _ => return Err(AlgoError::CorruptData("unknown split direction")),
+        };
+        let mut t = Self::with_split(split);
+        let (n, reserve) = dec.count(24)?;
+        t.root = dec.u32()?;
+        t.bounds = match dec.u8()? {
+            0 => None,
+            1 => Some(RectHV::new(dec.f64()?, dec.f64()?, dec.f64()?, dec.f64()?)),
+            _ => return Err(AlgoError::CorruptData("bad bounds flag")),
+        };
+        t.nodes.reserve(reserve);
+        for _ in 0..n {
+            let point = dec.point()?;
+            let (left, right) = (dec.u32()?, dec.u32()?);
+            t.nodes.push(Node {
+                point,
+                value: (),
+                left,
+                right,
+            });
+        }
+        dec.finish()?;
+        t.check_shape()?;
+        Ok(t)
+    }
+
+    // The checksum only proves the file is the one that was written. Queries index
+    // nodes by their links and prune by rectangles without checking either, so make
+    // sure the links form one tree over all the nodes, that no point is there twice
+    // and that every point is on its side of each split above it: strictly below on
+    // the left, at or above on the right.
+    fn check_shape(&self) -> Result<(), AlgoError> {
+        let corrupt = Err(AlgoError::CorruptData("nodes don't form a kd-tree"));
+        if self.nodes.len() >= NIL as usize {
+            return corrupt;
+        }
+        let Some((root, split, rect)) = self.root_entry() else {
+            return if self.nodes.is_empty() && self.root == NIL {
+                Ok(())
+            } else {
+                corrupt
+            };
+        };
+        let mut seen = vec![false; self.nodes.len()];
+        let mut points = HashSet::with_capacity(self.nodes.len());
+        // the rect's top and right edges are left out once a left branch is taken
+        // across them; its other edges are always in
+        let mut stack = vec![(root, split, rect, false, false)];
+        while let Some((i, split, rect, x_open, y_open)) = stack.pop() {
+            match seen.get_mut(i as usize) {
+                Some(s) if !*s => *s = true,
+                _ => return corrupt,
+            }
+            let node = self.node(i);
+            let p = &node.point;
+            if !rect.contains(p)
+                || (x_open && p.x() >= rect.xmax())
+                || (y_open && p.y() >= rect.ymax())
+                || !points.insert(p)
+            {
+                return corrupt;
+            }
+            let (lr, rr) = split.split_rect(p, &rect);
+            let (lx, ly) = match split {
+                SplitHV::V => (true, y_open),
+                SplitHV::H => (x_open, true),
+            };
+            if node.left != NIL {
+                stack.push((node.left, split.flip(), lr, lx, ly));
+            }
+            if node.right != NIL {
+                stack.push((node.right, split.flip(), rr, x_open, y_open));
+            }
+        }
+        if seen.contains(&false) {
+            return corrupt;
+        }
+        Ok(())
+    }
+}
+
+fn cpus() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// answer the queries in order, a contiguous chunk on each of up to threads threads.
+// Queries only read the tree, so the threads can all share it.
+fn batch<T: Send>(
+    queries: &[Point2D],
+    threads: usize,
+    answer: impl Fn(&Point2D) -> T + Sync,
+) -> Vec<T> {
+    // with fewer queries than this per thread, spawning costs more than it saves
+    const MIN_CHUNK: usize = 1024;
+    let chunk = queries.len().div_ceil(threads).max(MIN_CHUNK);
+    if queries.len() <= chunk {
+        return queries.iter().map(answer).collect();
+    }
+    thread::scope(|s| {
+        let workers: Vec<_> = queries
+            .chunks(chunk)
+            .map(|c| s.spawn(|| c.iter().map(&answer).collect::<Vec<_>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect()
+    })
+}
+
+pub struct RangeIter<'a, V, R, O = ()> {
+    tree: &'a KdTreeST<V>,
+    region: R,
+    observer: O,
+    // subtrees still to visit, with their splits and rectangles
+    stack: Vec<(u32, SplitHV, RectHV)>,
+}
+
+impl<'a, V, R: Region, O: QueryObserver> Iterator for RangeIter<'a, V, R, O> {
+    type Item = (&'a Point2D, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        while let Some((i, split, rect)) = self.stack.pop() {
+            let node = tree.node(i);
+            self.observer.visit(&node.point, split, &rect);
+            let (left_rect, right_rect) = split.split_rect(&node.point, &rect);
+            // push right first so the left subtree comes out first
+            for (child, child_rect) in [(node.right, right_rect), (node.left, left_rect)] {
+                if child == NIL {
+                    continue;
+                }
+                if self.region.intersects(&child_rect) {
+                    self.stack.push((child, split.flip(), child_rect));
+                } else {
+                    self.observer.prune(&child_rect);
+                }
+            }
+            if self.region.contains(&node.point) {
+                return Some((&node.point, &node.value));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{kdtrees::point_st::PointST, util::region::Circle};
+
+    // coordinates on a coarse grid so that points repeat and splits often tie
+    fn grid_point(rng: &mut StdRng) -> Point2D {
+        Point2D::new(
+            rng.gen_range(0..20) as f64 / 4.0,
+            rng.gen_range(0..20) as f64 / 4.0,
+        )
+    }
+
+    #[test]
+    fn test_balanced_repeats() {
+        // however often a point repeats, it is one node, with the last value
+        let mut entries: Vec<(Point2D, usize)> =
+            (0..100_000).map(|i| (Point2D::new(0.5, 0.5), i)).collect();
+        entries.extend((0..7).map(|i| (Point2D::new(i as f64, 0.0), i)));
+        let tree = KdTreeST::balanced(SplitHV::V, entries);
+        assert_eq!(tree.size(), 8);
+        assert_eq!(tree.height(), 4);
+        assert_eq!(tree.get(Point2D::new(0.5, 0.5)), Some(&99_999));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let entries: Vec<(Point2D, usize)> =
+            (0..10_000).map(|i| (grid_point(&mut rng), i)).collect();
+        let mut st = PointST::new();
+        entries.iter().for_each(|(p, v)| st.put(p.clone(), *v));
+        let tree = KdTreeST::balanced(SplitHV::H, entries);
+        assert_eq!(tree.size(), st.size());
+        // 400 grid points, balanced but for ties on the split coordinates
+        assert!(tree.height() <= 2 * 9, "{}", tree.height());
+        for (p, v) in st.all() {
+            assert_eq!(tree.get(p.clone()), Some(v));
+        }
+    }
+
+    #[test]
+    fn test() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut tree = KdTreeST::new();
+        let mut st = PointST::new();
+        assert_eq!(tree.nearest(Point2D::new(0.0, 0.0)), None);
+        for step in 0..5000 {
+            let p = grid_point(&mut rng);
+            if rng.gen_bool(0.7) {
+                tree.put(p.clone(), step);
+                st.put(p, step);
+            } else {
+                assert_eq!(tree.remove(p.clone()), st.remove(p));
+            }
+            assert_eq!(tree.size(), st.size());
+            if step % 50 == 0 {
+                for (p, v) in st.all() {
+                    assert_eq!(tree.get(p.clone()), Some(v));
+                }
+                let q = grid_point(&mut rng);
+                assert_eq!(tree.nearest(q.clone()), st.nearest(q.clone()));
+                assert_eq!(tree.nearest_k(q.clone(), 6), st.nearest_k(q.clone(), 6));
+                let circle = Circle::new(q, 1.5);
+                let mut got = tree.range(&circle);
+                got.sort();
+                assert_eq!(got, st.range(&circle));
+            }
+        }
+        tree.rebalance();
+        let mut got = tree.all();
+        got.sort();
+        assert_eq!(got, st.all());
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut tree = KdTreeST::new();
+        for i in 0..1000 {
+            tree.put(grid_point(&mut rng), i);
+        }
+        let queries: Vec<Point2D> = (0..5000).map(|_| grid_point(&mut rng)).collect();
+        let expected: Vec<_> = queries.iter().map(|q| tree.nearest(q.clone())).collect();
+        for threads in [1, 3, 8] {
+            assert_eq!(
+                batch(&queries, threads, |q| tree.nearest(q.clone())),
+                expected
+            );
+        }
+        assert_eq!(tree.nearest_many(&queries), expected);
+    }
+}
@@ -1,30 +1,28 @@
 use std::{
-    fs::File,
+    fs::{self, File},
     io::{self, BufRead},
 };
 
-use crate::util::{point2d::Point2D, rect_hv::RectHV, std_draw::Plot};
-
-use self::{
-    kdtree::{KdTree, SplitHV},
-    point_set::PointSET,
+use crate::{
+    error::AlgoError,
+    util::{point2d::Point2D, rect_hv::RectHV, std_draw::Plot},
 };
 
+use self::{kdtree::KdTree, point_set::PointSET, query_stats::VisitedNodes};
+
 pub mod kdtree;
+pub mod kdtree_n;
+pub mod kdtree_st;
+mod persist;
+pub mod point_index;
 pub mod point_set;
+pub mod point_st;
+pub mod query_stats;
 
 pub fn run_point_set(filename: String) -> anyhow::Result<()> {
-    let file = File::open(filename)?;
-    let lines = io::BufReader::new(file).lines();
     let mut points = PointSET::new();
-    for row in lines.flatten() {
-        let nums: Vec<f64> = row
-            .split_ascii_whitespace()
-            .map(|n| n.parse::<f64>().unwrap())
-            .collect();
-        if nums.len() == 2 {
-            points.insert(Point2D::new(nums[0], nums[1]));
-        }
+    for p in read_points(filename)? {
+        points.insert(p);
     }
 
     let mut plot = Plot::new()?;
@@ -42,7 +40,8 @@ pub fn run_point_set(filename: String) -> anyhow::Result<()> {
     }
 
     // find nearest point to 0.5,0.5
-    let p = points.nearest(Point2D::new(0.5, 0.5));
+    let (p, stats) = points.nearest_with_stats(Point2D::new(0.5, 0.5));
+    println!("nearest: {:?}", stats);
     if let Some(near) = p {
         plot.line(0.5, 0.5, near.x(), near.y())?;
     }
@@ -52,19 +51,53 @@ pub fn run_point_set(filename: String) -> anyhow::Result<()> {
     anyhow::Ok(())
 }
 
-pub fn run_kdtree(filename: String) -> anyhow::Result<()> {
+pub(crate) fn read_points(filename: String) -> anyhow::Result<Vec<Point2D>> {
     let file = File::open(filename)?;
     let lines = io::BufReader::new(file).lines();
-    let mut points = KdTree::new(SplitHV::V);
+    let mut input = vec![];
     for row in lines.flatten() {
         let nums: Vec<f64> = row
             .split_ascii_whitespace()
             .map(|n| n.parse::<f64>().unwrap())
             .collect();
         if nums.len() == 2 {
-            points.insert(Point2D::new(nums[0], nums[1]));
+            input.push(Point2D::new(nums[0], nums[1]));
         }
     }
+    Ok(input)
+}
+
+// the tree of the points in filename. It's saved next to the file the first time,
+// and loaded from there while the input is unchanged rather than built again.
+// The saved copy is only a cache: if it can't be read or written (say the input
+// sits in a read-only directory) that is reported and the run goes on.
+fn load_kdtree(filename: String) -> anyhow::Result<KdTree> {
+    let saved = format!("{filename}.kdtree");
+    let modified = |f: &str| fs::metadata(f).and_then(|m| m.modified());
+    if let (Ok(input), Ok(tree)) = (modified(&filename), modified(&saved)) {
+        if tree >= input {
+            match File::open(&saved)
+                .map_err(AlgoError::from)
+                .and_then(KdTree::load)
+            {
+                Ok(points) => return Ok(points),
+                Err(e) => println!("rebuilding {saved}: {e}"),
+            }
+        }
+    }
+    // bulk load rather than insert one at a time, the input files can be sorted
+    let points = KdTree::from_points(read_points(filename)?);
+    if let Err(e) = File::create(&saved)
+        .map_err(AlgoError::from)
+        .and_then(|f| points.save(f))
+    {
+        println!("not saving {saved}: {e}");
+    }
+    Ok(points)
+}
+
+pub fn run_kdtree(filename: String) -> anyhow::Result<()> {
+    let points = load_kdtree(filename)?;
 
     let mut plot = Plot::new()?;
     plot.set_x_scale(0.0, 1.0);
@@ -81,7 +114,8 @@ pub fn run_kdtree(filename: String) -> anyhow::Result<()> {
     }
 
     // find nearest point to 0.5,0.5
-    let p = points.nearest(Point2D::new(0.5, 0.5));
+    let (p, stats) = points.nearest_with_stats(Point2D::new(0.5, 0.5));
+    println!("nearest: {:?}", stats);
     if let Some(near) = p {
         plot.line(0.5, 0.5, near.x(), near.y())?;
     }
@@ -90,3 +124,26 @@ pub fn run_kdtree(filename: String) -> anyhow::Result<()> {
 
     anyhow::Ok(())
 }
+
+// like the KdTreeVisualizer: the splits of the tree, then the nodes a nearest
+// neighbour search visited and the subtrees it pruned
+pub fn run_kdtree_visualizer(filename: String) -> anyhow::Result<()> {
+    let points = load_kdtree(filename)?;
+
+    let mut plot = Plot::new()?;
+    plot.set_x_scale(0.0, 1.0);
+    plot.set_y_scale(0.0, 1.0);
+
+    points.draw_splits(&mut plot)?;
+
+    let query = Point2D::new(0.5, 0.5);
+    let mut visited = VisitedNodes::new();
+    if let Some(near) = points.nearest_observed(query.clone(), &mut visited) {
+        visited.draw(&mut plot)?;
+        plot.line(query.x(), query.y(), near.x(), near.y())?;
+    }
+
+    plot.present()?;
+
+    anyhow::Ok(())
+}
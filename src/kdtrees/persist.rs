@@ -0,0 +1,318 @@
+/* Binary files for the point indexes, so a big input is parsed and built once and
+afterwards loaded as it was saved, tree shape and all. Everything is little endian:
+
+    magic (4 bytes) | version (u32) | body | checksum (u64)
+
+The magic says which index the file holds, the body is that index's own (see
+KdTreeST::encode and PointSET::save) and the checksum is FNV-1a over every byte
+before it. Loading checks all three and then the body's structure, so a damaged
+or foreign file is an error rather than a panic or a tree that gives wrong answers. */
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::{error::AlgoError, util::point2d::Point2D};
+
+pub(super) const VERSION: u32 = 1;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv(mut hash: u64, bytes: &[u8]) -> u64 {
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+pub(super) struct Encoder<W: Write> {
+    w: BufWriter<W>,
+    hash: u64,
+}
+
+impl<W: Write> Encoder<W> {
+    // start a file of the kind given by magic
+    pub(super) fn new(w: W, magic: &[u8; 4]) -> io::Result<Self> {
+        let mut enc = Self {
+            w: BufWriter::new(w),
+            hash: FNV_OFFSET,
+        };
+        enc.bytes(magic)?;
+        enc.u32(VERSION)?;
+        Ok(enc)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.hash = fnv(self.hash, bytes);
+        self.w.write_all(bytes)
+    }
+
+    pub(super) fn u8(&mut self, n: u8) -> io::Result<()> {
+        self.bytes(&[n])
+    }
+
+    pub(super) fn u32(&mut self, n: u32) -> io::Result<()> {
+        self.bytes(&n.to_le_bytes())
+    }
+
+    pub(super) fn u64(&mut self, n: u64) -> io::Result<()> {
+        self.bytes(&n.to_le_bytes())
+    }
+
+    pub(super) fn f64(&mut self, x: f64) -> io::Result<()> {
+        self.bytes(&x.to_le_bytes())
+    }
+
+    pub(super) fn point(&mut self, p: &Point2D) -> io::Result<()> {
+        self.f64(p.x())?;
+        self.f64(p.y())
+    }
+
+    // write the checksum and flush
+    pub(super) fn finish(mut self) -> io::Result<()> {
+        let hash = self.hash;
+        self.w.write_all(&hash.to_le_bytes())?;
+        self.w.flush()
+    }
+}
+
+pub(super) struct Decoder<R: Read> {
+    r: BufReader<R>,
+    hash: u64,
+}
+
+impl<R: Read> Decoder<R> {
+    // read the header of a file that should be of the kind given by magic
+    pub(super) fn new(r: R, magic: &[u8; 4]) -> Result<Self, AlgoError> {
+        let mut dec = Self {
+            r: BufReader::new(r),
+            hash: FNV_OFFSET,
+        };
+        if dec.bytes::<4>()? != *magic {
+            return Err(AlgoError::CorruptData("wrong magic number"));
+        }
+        match dec.u32()? {
+            VERSION => Ok(dec),
+            v => Err(AlgoError::UnsupportedVersion(v)),
+        }
+    }
+
+    fn raw<const N: usize>(&mut self) -> Result<[u8; N], AlgoError> {
+        let mut buf = [0; N];
+        self.r.read_exact(&mut buf).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => AlgoError::CorruptData("file is truncated"),
+            _ => AlgoError::Io(e),
+        })?;
+        Ok(buf)
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], AlgoError> {
+        let buf = self.raw()?;
+        self.hash = fnv(self.hash, &buf);
+        Ok(buf)
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8, AlgoError> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32, AlgoError> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    pub(super) fn u64(&mut self) -> Result<u64, AlgoError> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    pub(super) fn f64(&mut self) -> Result<f64, AlgoError> {
+        Ok(f64::from_le_bytes(self.bytes()?))
+    }
+
+    pub(super) fn point(&mut self) -> Result<Point2D, AlgoError> {
+        Ok(Point2D::new(self.f64()?, self.f64()?))
+    }
+
+    // a count of items, each at least item_size bytes: reserving room for them
+    // must not trust the count, a corrupt one could ask for any amount of memory
+    pub(super) fn count(&mut self, item_size: usize) -> Result<(usize, usize), AlgoError> {
+        let n = usize::try_from(self.u64()?)
+            .map_err(|_| AlgoError::CorruptData("count is too large"))?;
+        Ok((n, n.min((1 << 24) / item_size)))
+    }
+
+    // read the checksum and compare it with the one of everything read so far
+    pub(super) fn finish(mut self) -> Result<(), AlgoError> {
+        let hash = self.hash;
+        if u64::from_le_bytes(self.raw()?) != hash {
+            return Err(AlgoError::CorruptData("checksum mismatch"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::kdtrees::{
+        kdtree::{KdTree, SplitHV},
+        point_set::PointSET,
+    };
+
+    use super::*;
+
+    fn random_points(n: usize, rng: &mut StdRng) -> Vec<Point2D> {
+        (0..n).map(|_| Point2D::new(rng.gen(), rng.gen())).collect()
+    }
+
+    #[test]
+    fn test_kdtree() {
+        let mut rng = StdRng::seed_from_u64(46);
+        let mut built = KdTree::from_points(random_points(500, &mut rng));
+        let mut grown = KdTree::new(SplitHV::H);
+        for p in random_points(500, &mut rng) {
+            grown.insert(p);
+        }
+        for p in built
+            .all()
+            .into_iter()
+            .take(100)
+            .cloned()
+            .collect::<Vec<_>>()
+        {
+            built.remove(p);
+        }
+        // a grid ties on a coordinate at almost every split
+        let grid: Vec<Point2D> = (0..400)
+            .map(|i| Point2D::new((i % 20) as f64 / 20.0, (i / 20) as f64 / 20.0))
+            .collect();
+        let mut gridded = KdTree::new(SplitHV::V);
+        for p in grid.iter().rev() {
+            gridded.insert(p.clone());
+        }
+        for tree in [
+            built,
+            grown,
+            KdTree::from_points(grid),
+            gridded,
+            KdTree::new(SplitHV::V),
+        ] {
+            let mut file = vec![];
+            tree.save(&mut file).unwrap();
+            let loaded = KdTree::load(file.as_slice()).unwrap();
+            // the same shape, so the same walk for every query
+            assert_eq!(loaded.all(), tree.all());
+            assert_eq!(loaded.height(), tree.height());
+            assert_eq!(loaded.bounds(), tree.bounds());
+            for q in random_points(20, &mut rng) {
+                assert_eq!(
+                    loaded.nearest_with_stats(q.clone()).1.nodes_visited,
+                    tree.nearest_with_stats(q).1.nodes_visited
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_point_set() {
+        let mut rng = StdRng::seed_from_u64(47);
+        let mut set = PointSET::new();
+        for p in random_points(300, &mut rng) {
+            set.insert(p);
+        }
+        let mut file = vec![];
+        set.save(&mut file).unwrap();
+        let loaded = PointSET::load(file.as_slice()).unwrap();
+        assert_eq!(loaded.all(), set.all());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let tree = KdTree::from_points(random_points(50, &mut StdRng::seed_from_u64(48)));
+        let mut file = vec![];
+        tree.save(&mut file).unwrap();
+
+        // every truncation and every flipped bit is caught
+        for len in 0..file.len() {
+            let err = KdTree::load(&file[..len]).err().unwrap();
+            assert!(matches!(err, AlgoError::CorruptData(_)), "{len}: {err}");
+        }
+        for i in 0..file.len() {
+            for bit in 0..8 {
+                let mut bad = file.clone();
+                bad[i] ^= 1 << bit;
+                assert!(KdTree::load(bad.as_slice()).is_err());
+            }
+        }
+
+        let mut newer = file.clone();
+        newer[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            KdTree::load(newer.as_slice()),
+            Err(AlgoError::UnsupportedVersion(v)) if v == VERSION + 1
+        ));
+        // a kd-tree is not a point set
+        assert!(matches!(
+            PointSET::load(file.as_slice()),
+            Err(AlgoError::CorruptData(_))
+        ));
+    }
+
+    // a well formed file, checksum and all, of a V tree rooted at node 0 with these
+    // nodes, each as its point and its left and right children
+    fn tree_file(nodes: &[((f64, f64), u32, u32)]) -> Vec<u8> {
+        let mut file = vec![];
+        let mut enc = Encoder::new(&mut file, b"KDTR").unwrap();
+        enc.u8(b'V').unwrap();
+        enc.u64(nodes.len() as u64).unwrap();
+        enc.u32(0).unwrap();
+        enc.u8(1).unwrap();
+        for x in [0.0, 0.0, 1.0, 1.0] {
+            enc.f64(x).unwrap();
+        }
+        for &((x, y), left, right) in nodes {
+            enc.point(&Point2D::new(x, y)).unwrap();
+            enc.u32(left).unwrap();
+            enc.u32(right).unwrap();
+        }
+        enc.finish().unwrap();
+        file
+    }
+
+    #[test]
+    fn test_bad_structure() {
+        const NIL: u32 = u32::MAX;
+        let good = tree_file(&[
+            ((0.5, 0.5), 1, 2),
+            ((0.2, 0.7), NIL, NIL),
+            ((0.5, 0.1), NIL, NIL),
+        ]);
+        assert_eq!(KdTree::load(good.as_slice()).unwrap().size(), 3);
+
+        for bad in [
+            // the root is its own child
+            vec![((0.5, 0.5), 0, NIL)],
+            // a node nobody links to
+            vec![((0.5, 0.5), NIL, NIL), ((0.2, 0.2), NIL, NIL)],
+            // outside the bounds
+            vec![((1.5, 0.5), NIL, NIL)],
+            // on the split line, which belongs to the right
+            vec![((0.5, 0.5), 1, NIL), ((0.5, 0.7), NIL, NIL)],
+            // on the wrong side of a split two levels up
+            vec![
+                ((0.5, 0.5), 1, NIL),
+                ((0.2, 0.5), NIL, 2),
+                ((0.5, 0.6), NIL, NIL),
+            ],
+            // the same point twice
+            vec![((0.5, 0.5), NIL, 1), ((0.5, 0.5), NIL, NIL)],
+        ] {
+            assert!(
+                matches!(
+                    KdTree::load(tree_file(&bad).as_slice()),
+                    Err(AlgoError::CorruptData(_))
+                ),
+                "{bad:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::util::{point2d::Point2D, region::Region};
+
+/// The operations every set of points supports, whatever it is built on. The
+/// brute-force PointSET answers them all by definition, so any other
+/// implementation can be checked against it.
+pub trait PointIndex {
+    // is the set empty?
+    fn is_empty(&self) -> bool;
+
+    // number of points in the set
+    fn size(&self) -> usize;
+
+    // add the point to the set (if it is not already in the set)
+    fn insert(&mut self, p: Point2D);
+
+    // remove the point from the set; false if it wasn't there
+    fn remove(&mut self, p: Point2D) -> bool;
+
+    // does the set contain point p?
+    fn contains(&self, p: Point2D) -> bool;
+
+    // all points that are inside the region (or on the boundary), in any order
+    fn range(&self, region: &dyn Region) -> Vec<&Point2D>;
+
+    // the nearest neighbor to point p (of those equally near, the smallest point);
+    // None if the set is empty
+    fn nearest(&self, p: Point2D) -> Option<&Point2D>;
+
+    // the k points closest to p, nearest first (ties go to the smaller point)
+    fn nearest_k(&self, p: Point2D, k: usize) -> Vec<&Point2D>;
+}
+
+/// Differential test: build an index and a PointSET from the same points, run
+/// the same random workload of updates and queries on both, and panic at the
+/// first answer that differs. Points come from a coarse grid, so there are
+/// plenty of repeats, ties along splits and equally near neighbours, mixed with
+/// arbitrary coordinates that reach outside the unit square.
+#[cfg(test)]
+pub(crate) fn check_against_point_set<I: PointIndex>(
+    build: impl Fn(Vec<Point2D>) -> I,
+    seed: u64,
+    steps: usize,
+) {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::point_set::PointSET;
+    use crate::util::{rect_hv::RectHV, region::Circle};
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let random_point = |rng: &mut StdRng| {
+        if rng.gen_bool(0.7) {
+            Point2D::new(
+                rng.gen_range(0..9) as f64 / 8.0,
+                rng.gen_range(0..9) as f64 / 8.0,
+            )
+        } else {
+            Point2D::new(rng.gen_range(-0.5..1.5), rng.gen_range(-0.5..1.5))
+        }
+    };
+    let sorted = |mut v: Vec<&Point2D>| {
+        v.sort();
+        v.into_iter().cloned().collect::<Vec<_>>()
+    };
+
+    let initial: Vec<Point2D> = (0..rng.gen_range(0..100))
+        .map(|_| random_point(&mut rng))
+        .collect();
+    let mut expected = PointSET::new();
+    initial.iter().for_each(|p| expected.insert(p.clone()));
+    let mut index = build(initial);
+
+    for step in 0..steps {
+        let p = random_point(&mut rng);
+        match rng.gen_range(0..10) {
+            0..=3 => {
+                index.insert(p.clone());
+                expected.insert(p);
+            }
+            4 => assert_eq!(index.remove(p.clone()), expected.remove(p), "step {step}"),
+            5 => assert_eq!(
+                index.contains(p.clone()),
+                expected.contains(p),
+                "step {step}"
+            ),
+            6 => {
+                let q = random_point(&mut rng);
+                let rect = RectHV::new(
+                    p.x().min(q.x()),
+                    p.y().min(q.y()),
+                    p.x().max(q.x()),
+                    p.y().max(q.y()),
+                );
+                assert_eq!(
+                    sorted(index.range(&rect)),
+                    sorted(expected.range(&rect)),
+                    "step {step}: {rect:?}"
+                );
+            }
+            7 => {
+                let circle = Circle::new(p, rng.gen_range(0.0..0.5));
+                assert_eq!(
+                    sorted(index.range(&circle)),
+                    sorted(expected.range(&circle)),
+                    "step {step}: {circle:?}"
+                );
+            }
+            8 => assert_eq!(
+                index.nearest(p.clone()),
+                expected.nearest(p.clone()),
+                "step {step}: {p:?}"
+            ),
+            _ => {
+                let k = rng.gen_range(0..10);
+                assert_eq!(
+                    index.nearest_k(p.clone(), k),
+                    expected.nearest_k(p.clone(), k),
+                    "step {step}: {p:?}"
+                );
+            }
+        }
+        assert_eq!(index.size(), expected.size(), "step {step}");
+        assert_eq!(index.is_empty(), expected.is_empty(), "step {step}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kdtrees::kdtree::{KdTree, SplitHV};
+
+    #[test]
+    fn test_kdtree() {
+        for seed in 0..10 {
+            for split in [SplitHV::V, SplitHV::H] {
+                check_against_point_set(
+                    |points| {
+                        let mut tree = KdTree::new(split);
+                        points.into_iter().for_each(|p| tree.insert(p));
+                        tree
+                    },
+                    seed,
+                    1000,
+                );
+            }
+            check_against_point_set(KdTree::from_points, seed, 1000);
+        }
+    }
+}
@@ -1,8 +1,24 @@
-use std::{collections::BTreeSet, time::Instant};
+use std::{
+    collections::BTreeSet,
+    io::{Read, Write},
+};
 
+use float_ord::FloatOrd;
 use itertools::Itertools;
 
-use crate::util::{point2d::Point2D, rect_hv::RectHV, std_draw::Plot};
+use crate::{
+    error::AlgoError,
+    util::{point2d::Point2D, region::Region, std_draw::Plot},
+};
+
+use super::{
+    persist::{Decoder, Encoder},
+    point_index::PointIndex,
+    query_stats::QueryStats,
+};
+
+// file type of a saved PointSET
+const MAGIC: &[u8; 4] = b"PSET";
 
 pub struct PointSET {
     set: BTreeSet<Point2D>,
@@ -31,11 +47,25 @@ impl PointSET {
         self.set.insert(p);
     }
 
+    // remove the point from the set; false if it wasn't there
+    pub fn remove(&mut self, p: Point2D) -> bool {
+        self.set.remove(&p)
+    }
+
     // does the set contain point p?
     pub fn contains(&self, p: Point2D) -> bool {
         self.set.contains(&p)
     }
 
+    // contains, with the cost of the search. The stats count the set as the brute
+    // force the kd-tree is measured against: every point visited, nothing pruned
+    pub fn contains_with_stats(&self, p: Point2D) -> (bool, QueryStats) {
+        QueryStats::measure(|stats| {
+            stats.nodes_visited = self.set.len();
+            self.contains(p)
+        })
+    }
+
     // draw all points to standard draw
     pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
         for p in self.set.iter() {
@@ -44,36 +74,130 @@ impl PointSET {
         Ok(())
     }
 
-    // all points that are inside the rectangle (or on the boundary)
-    pub fn range(&self, rect: RectHV) -> Vec<Point2D> {
+    // all points that are inside the region (or on the boundary)
+    pub fn range<R: Region>(&self, region: R) -> Vec<&Point2D> {
         let mut v = vec![];
         for p in self.set.iter() {
-            if rect.contains(p) {
-                v.push(p.clone());
+            if region.contains(p) {
+                v.push(p);
             }
         }
         v
     }
 
-    // a nearest neighbor in the set to point p; null if the set is empty
+    // range, with the cost of the search
+    pub fn range_with_stats<R: Region>(&self, region: R) -> (Vec<&Point2D>, QueryStats) {
+        QueryStats::measure(|stats| {
+            stats.nodes_visited = self.set.len();
+            self.range(region)
+        })
+    }
+
+    // lazily iterate over the points inside the region
+    pub fn range_iter<R: Region>(&self, region: R) -> impl Iterator<Item = &Point2D> {
+        self.set.iter().filter(move |p| region.contains(p))
+    }
+
+    // a nearest neighbor in the set to point p (of those equally near, the smallest
+    // point); null if the set is empty
     pub fn nearest(&self, p: Point2D) -> Option<&Point2D> {
-        let start = Instant::now();
         let mut near: Option<&Point2D> = None;
-        let mut dist: f64 = f64::MAX;
+        let mut dist: f64 = f64::INFINITY;
         for other in self.set.iter() {
-            let d = p.distance_to(other);
+            // squared, as sqrt could round two different distances to the same value
+            let d = p.distance_squared_to(other);
             if d < dist {
                 dist = d;
                 near = Some(other);
             }
         }
-        let duration = start.elapsed();
-        println!("{:?}", duration);
-
         near
     }
 
+    // nearest, with the cost of the search: a distance to every point
+    pub fn nearest_with_stats(&self, p: Point2D) -> (Option<&Point2D>, QueryStats) {
+        QueryStats::measure(|stats| {
+            stats.nodes_visited = self.set.len();
+            stats.distance_computations = self.set.len();
+            self.nearest(p)
+        })
+    }
+
+    // the k points in the set closest to p, nearest first (ties go to the smaller point)
+    pub fn nearest_k(&self, p: Point2D, k: usize) -> Vec<&Point2D> {
+        let mut v = self
+            .set
+            .iter()
+            .map(|other| (FloatOrd(p.distance_squared_to(other)), other))
+            .collect_vec();
+        v.sort();
+        v.into_iter().take(k).map(|(_, other)| other).collect()
+    }
+
     pub fn all(&self) -> Vec<&Point2D> {
         self.set.iter().collect_vec()
     }
+
+    // write the set in the binary format of persist: the count, then the points
+    // in order
+    pub fn save<W: Write>(&self, w: W) -> Result<(), AlgoError> {
+        let mut enc = Encoder::new(w, MAGIC)?;
+        enc.u64(self.set.len() as u64)?;
+        for p in self.set.iter() {
+            enc.point(p)?;
+        }
+        Ok(enc.finish()?)
+    }
+
+    // read a set written by save
+    pub fn load<R: Read>(r: R) -> Result<Self, AlgoError> {
+        let mut dec = Decoder::new(r, MAGIC)?;
+        let (n, reserve) = dec.count(16)?;
+        let mut points = Vec::with_capacity(reserve);
+        for _ in 0..n {
+            points.push(dec.point()?);
+        }
+        dec.finish()?;
+        // saved in order, so the set is built from them in one pass, no inserts
+        if !points.windows(2).all(|w| w[0] < w[1]) {
+            return Err(AlgoError::CorruptData("points are out of order"));
+        }
+        Ok(Self {
+            set: points.into_iter().collect(),
+        })
+    }
+}
+
+impl PointIndex for PointSET {
+    fn is_empty(&self) -> bool {
+        PointSET::is_empty(self)
+    }
+
+    fn size(&self) -> usize {
+        PointSET::size(self)
+    }
+
+    fn insert(&mut self, p: Point2D) {
+        PointSET::insert(self, p)
+    }
+
+    fn remove(&mut self, p: Point2D) -> bool {
+        PointSET::remove(self, p)
+    }
+
+    fn contains(&self, p: Point2D) -> bool {
+        PointSET::contains(self, p)
+    }
+
+    fn range(&self, region: &dyn Region) -> Vec<&Point2D> {
+        PointSET::range(self, region)
+    }
+
+    fn nearest(&self, p: Point2D) -> Option<&Point2D> {
+        PointSET::nearest(self, p)
+    }
+
+    fn nearest_k(&self, p: Point2D, k: usize) -> Vec<&Point2D> {
+        PointSET::nearest_k(self, p, k)
+    }
 }
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use float_ord::FloatOrd;
+use itertools::Itertools;
+
+use crate::util::{point2d::Point2D, region::Region, std_draw::Plot};
+
+/// Brute-force symbol table from points to values, the reference for KdTreeST.
+pub struct PointST<V> {
+    map: BTreeMap<Point2D, V>,
+}
+
+impl<V> Default for PointST<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> PointST<V> {
+    // construct an empty symbol table of points
+    pub fn new() -> Self {
+        Self {
+            map: BTreeMap::new(),
+        }
+    }
+
+    // is the symbol table empty?
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    // number of points in the symbol table
+    pub fn size(&self) -> usize {
+        self.map.len()
+    }
+
+    // associate the value with point p, replacing any value p already had
+    pub fn put(&mut self, p: Point2D, value: V) {
+        self.map.insert(p, value);
+    }
+
+    // value associated with point p
+    pub fn get(&self, p: Point2D) -> Option<&V> {
+        self.map.get(&p)
+    }
+
+    // does the symbol table contain point p?
+    pub fn contains(&self, p: Point2D) -> bool {
+        self.map.contains_key(&p)
+    }
+
+    // remove point p and return its value; None if it wasn't there
+    pub fn remove(&mut self, p: Point2D) -> Option<V> {
+        self.map.remove(&p)
+    }
+
+    // draw all points to standard draw
+    pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        for p in self.map.keys() {
+            p.draw(plot)?;
+        }
+        Ok(())
+    }
+
+    // all entries whose points are inside the region (or on the boundary)
+    pub fn range<R: Region>(&self, region: R) -> Vec<(&Point2D, &V)> {
+        self.map
+            .iter()
+            .filter(|(p, _)| region.contains(p))
+            .collect()
+    }
+
+    // the nearest neighbor of point p and its value (of those equally near, the
+    // smallest point); None if the symbol table is empty
+    pub fn nearest(&self, p: Point2D) -> Option<(&Point2D, &V)> {
+        self.nearest_k(p, 1).pop()
+    }
+
+    // the k entries closest to p, nearest first (ties go to the smaller point)
+    pub fn nearest_k(&self, p: Point2D, k: usize) -> Vec<(&Point2D, &V)> {
+        let mut v = self
+            .map
+            .iter()
+            .map(|(other, value)| (FloatOrd(p.distance_squared_to(other)), other, value))
+            .collect_vec();
+        v.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        v.into_iter()
+            .take(k)
+            .map(|(_, other, value)| (other, value))
+            .collect()
+    }
+
+    // every entry, in order of point
+    pub fn all(&self) -> Vec<(&Point2D, &V)> {
+        self.map.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::rect_hv::RectHV;
+
+    #[test]
+    fn test() {
+        let mut st = PointST::new();
+        st.put(Point2D::new(0.1, 0.1), "a");
+        st.put(Point2D::new(0.9, 0.9), "b");
+        st.put(Point2D::new(0.1, 0.1), "c");
+        assert_eq!(st.size(), 2);
+        assert_eq!(st.get(Point2D::new(0.1, 0.1)), Some(&"c"));
+        assert_eq!(
+            st.nearest(Point2D::new(0.7, 0.8)),
+            Some((&Point2D::new(0.9, 0.9), &"b"))
+        );
+        assert_eq!(st.range(RectHV::new(0.0, 0.0, 0.5, 0.5)).len(), 1);
+        assert_eq!(st.remove(Point2D::new(0.1, 0.1)), Some("c"));
+        assert_eq!(st.remove(Point2D::new(0.1, 0.1)), None);
+        assert_eq!(st.size(), 1);
+    }
+}
@@ -0,0 +1,212 @@
+use std::time::{Duration, Instant};
+
+use plotters::style::{RGBColor, BLUE};
+
+use crate::util::{point2d::Point2D, rect_hv::RectHV, std_draw::Plot};
+
+use super::kdtree::SplitHV;
+
+/// Callbacks from inside a kd-tree query, for instrumentation and visualisation.
+/// Every method does nothing by default, and `()` is the observer that ignores
+/// everything.
+pub trait QueryObserver {
+    // the query looked at the node holding point, split by split, whose subtree
+    // lies within rect
+    fn visit(&mut self, _point: &Point2D, _split: SplitHV, _rect: &RectHV) {}
+
+    // the query skipped the subtree within rect without looking inside
+    fn prune(&mut self, _rect: &RectHV) {}
+
+    // the query computed the distance from the query point to a point in the tree
+    fn distance(&mut self) {}
+}
+
+impl QueryObserver for () {}
+
+impl<O: QueryObserver + ?Sized> QueryObserver for &mut O {
+    fn visit(&mut self, point: &Point2D, split: SplitHV, rect: &RectHV) {
+        (**self).visit(point, split, rect)
+    }
+
+    fn prune(&mut self, rect: &RectHV) {
+        (**self).prune(rect)
+    }
+
+    fn distance(&mut self) {
+        (**self).distance()
+    }
+}
+
+/// What a query cost: counts from a QueryObserver plus the time it took.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueryStats {
+    pub nodes_visited: usize,
+    // subtrees skipped
+    pub nodes_pruned: usize,
+    // point to point distances, not the ones to rectangles used for pruning
+    pub distance_computations: usize,
+    pub elapsed: Duration,
+}
+
+impl QueryStats {
+    // run the query with a fresh QueryStats observing it, and time it
+    pub fn measure<T>(query: impl FnOnce(&mut QueryStats) -> T) -> (T, QueryStats) {
+        let mut stats = QueryStats::default();
+        let start = Instant::now();
+        let result = query(&mut stats);
+        stats.elapsed = start.elapsed();
+        (result, stats)
+    }
+}
+
+impl QueryObserver for QueryStats {
+    fn visit(&mut self, _point: &Point2D, _split: SplitHV, _rect: &RectHV) {
+        self.nodes_visited += 1;
+    }
+
+    fn prune(&mut self, _rect: &RectHV) {
+        self.nodes_pruned += 1;
+    }
+
+    fn distance(&mut self) {
+        self.distance_computations += 1;
+    }
+}
+
+/// Records the nodes a query visits and the subtrees it prunes, to draw them.
+#[derive(Debug, Default, Clone)]
+pub struct VisitedNodes {
+    visited: Vec<(Point2D, SplitHV, RectHV)>,
+    pruned: Vec<RectHV>,
+}
+
+impl VisitedNodes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the visited nodes' points, splits and rectangles, in the order visited
+    pub fn visited(&self) -> &[(Point2D, SplitHV, RectHV)] {
+        &self.visited
+    }
+
+    // rectangles of the pruned subtrees
+    pub fn pruned(&self) -> &[RectHV] {
+        &self.pruned
+    }
+
+    // draw the outline of every pruned subtree in grey, then the split line of
+    // every visited node with its point highlighted
+    pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        plot.set_pen_color(RGBColor(192, 192, 192));
+        for rect in self.pruned.iter() {
+            rect.draw(plot)?;
+        }
+        for (p, split, rect) in self.visited.iter() {
+            split.draw(p, rect, plot)?;
+        }
+        plot.set_pen_color(BLUE);
+        for (p, _, _) in self.visited.iter() {
+            p.draw_match(plot)?;
+        }
+        Ok(())
+    }
+}
+
+impl QueryObserver for VisitedNodes {
+    fn visit(&mut self, point: &Point2D, split: SplitHV, rect: &RectHV) {
+        self.visited.push((point.clone(), split, rect.clone()));
+    }
+
+    fn prune(&mut self, rect: &RectHV) {
+        self.pruned.push(rect.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::kdtrees::{kdtree::KdTree, point_set::PointSET};
+
+    #[test]
+    fn test() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let points: Vec<Point2D> = (0..10_000)
+            .map(|_| Point2D::new(rng.gen(), rng.gen()))
+            .collect();
+        let tree = KdTree::from_points(points.clone());
+
+        let q = Point2D::new(0.5, 0.5);
+        let (nearest, stats) = tree.nearest_with_stats(q.clone());
+        assert_eq!(nearest, tree.nearest(q));
+        assert!(stats.nodes_visited > 0 && stats.nodes_visited < 200);
+        assert_eq!(stats.distance_computations, stats.nodes_visited);
+        assert!(stats.nodes_pruned > 0);
+
+        let rect = RectHV::new(0.4, 0.4, 0.5, 0.5);
+        let (range, stats) = tree.range_with_stats(&rect);
+        assert_eq!(range, tree.range(&rect));
+        assert!(stats.nodes_visited >= range.len() && stats.nodes_visited < 1000);
+        assert_eq!(stats.distance_computations, 0);
+
+        let (found, stats) = tree.contains_with_stats(points[1234].clone());
+        assert!(found);
+        assert!(stats.nodes_visited <= tree.height());
+        let (found, stats) = tree.contains_with_stats(Point2D::new(2.0, 2.0));
+        assert!(!found);
+        assert!(stats.nodes_visited <= tree.height());
+    }
+
+    #[test]
+    fn test_point_set() {
+        let mut rng = StdRng::seed_from_u64(10);
+        let mut set = PointSET::new();
+        for _ in 0..1000 {
+            set.insert(Point2D::new(rng.gen(), rng.gen()));
+        }
+        let n = set.size();
+        // visited, pruned and distances: a brute force looks at every point
+        let counts = |s: QueryStats| (s.nodes_visited, s.nodes_pruned, s.distance_computations);
+
+        let q = Point2D::new(0.5, 0.5);
+        let (nearest, stats) = set.nearest_with_stats(q.clone());
+        assert_eq!(nearest, set.nearest(q.clone()));
+        assert_eq!(counts(stats), (n, 0, n));
+
+        let rect = RectHV::new(0.4, 0.4, 0.5, 0.5);
+        let (range, stats) = set.range_with_stats(&rect);
+        assert_eq!(range, set.range(&rect));
+        assert_eq!(counts(stats), (n, 0, 0));
+
+        let (found, stats) = set.contains_with_stats(Point2D::new(2.0, 2.0));
+        assert!(!found);
+        assert_eq!(counts(stats), (n, 0, 0));
+
+        let empty = PointSET::new();
+        let (nearest, stats) = empty.nearest_with_stats(q);
+        assert_eq!((nearest, counts(stats)), (None, (0, 0, 0)));
+    }
+
+    #[test]
+    fn test_visited_nodes() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let points: Vec<Point2D> = (0..1000)
+            .map(|_| Point2D::new(rng.gen(), rng.gen()))
+            .collect();
+        let tree = KdTree::from_points(points);
+        let q = Point2D::new(0.3, 0.7);
+        let mut visited = VisitedNodes::new();
+        let nearest = tree.nearest_observed(q.clone(), &mut visited);
+        let (_, stats) = tree.nearest_with_stats(q);
+        assert_eq!(visited.visited().len(), stats.nodes_visited);
+        assert_eq!(visited.pruned().len(), stats.nodes_pruned);
+        // the root comes first, and the answer is among the visited nodes
+        assert_eq!(Some(visited.visited()[0].2.clone()), tree.bounds().cloned());
+        assert!(visited.visited().iter().any(|(p, _, _)| Some(p) == nearest));
+        for (p, _, rect) in visited.visited() {
+            assert!(rect.contains(p));
+        }
+    }
+}
@@ -2,9 +2,12 @@ pub mod circular;
 pub mod collinear_points;
 pub mod eight_puzzle;
 pub mod error;
+pub mod geometry;
 pub mod kdtrees;
 pub mod percolation;
+pub mod quadtree;
 pub mod randomized_queue;
+pub mod rtree;
 pub mod sieve;
 pub mod union_find;
 pub mod util;
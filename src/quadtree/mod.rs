@@ -0,0 +1,38 @@
+use crate::{
+    kdtrees::read_points,
+    util::{point2d::Point2D, rect_hv::RectHV, std_draw::Plot},
+};
+
+use self::pr_quadtree::PRQuadTree;
+
+pub mod pr_quadtree;
+
+pub fn run_quadtree(filename: String) -> anyhow::Result<()> {
+    let mut points = PRQuadTree::new();
+    for p in read_points(filename)? {
+        points.insert(p);
+    }
+
+    let mut plot = Plot::new()?;
+    plot.set_x_scale(0.0, 1.0);
+    plot.set_y_scale(0.0, 1.0);
+
+    points.draw_cells(&mut plot)?;
+
+    // make a rect and find the points in it
+    let rect = RectHV::new(0.25, 0.25, 0.75, 0.75);
+    rect.draw(&mut plot)?;
+
+    for p in points.range(rect) {
+        p.draw_match(&mut plot)?;
+    }
+
+    // find nearest point to 0.5,0.5
+    if let Some(near) = points.nearest(Point2D::new(0.5, 0.5)) {
+        plot.line(0.5, 0.5, near.x(), near.y())?;
+    }
+
+    plot.present()?;
+
+    anyhow::Ok(())
+}
@@ -0,0 +1,640 @@
+use std::collections::BinaryHeap;
+
+use float_ord::FloatOrd;
+use plotters::style::{BLUE, RED};
+
+use crate::{
+    kdtrees::point_index::PointIndex,
+    util::{point2d::Point2D, rect_hv::RectHV, region::Region, std_draw::Plot},
+};
+
+enum Node {
+    Leaf(Vec<Point2D>),
+    // children by quadrant, see quadrant
+    Inner(Box<[Node; 4]>),
+}
+
+// which quadrant of rect p is in: bit 0 for the east half, bit 1 for the north
+// half. Points on a dividing line go east or north.
+fn quadrant(p: &Point2D, rect: &RectHV) -> usize {
+    let (mx, my) = center(rect);
+    (p.x() >= mx) as usize | ((p.y() >= my) as usize) << 1
+}
+
+// halfway along each side, which doesn't overflow as the sum of the corners can
+fn center(rect: &RectHV) -> (f64, f64) {
+    (
+        rect.xmin() + (rect.xmax() - rect.xmin()) / 2.0,
+        rect.ymin() + (rect.ymax() - rect.ymin()) / 2.0,
+    )
+}
+
+fn quadrant_rect(rect: &RectHV, q: usize) -> RectHV {
+    let (mx, my) = center(rect);
+    let (xmin, xmax) = if q & 1 == 0 {
+        (rect.xmin(), mx)
+    } else {
+        (mx, rect.xmax())
+    };
+    let (ymin, ymax) = if q & 2 == 0 {
+        (rect.ymin(), my)
+    } else {
+        (my, rect.ymax())
+    };
+    RectHV::new(xmin, ymin, xmax, ymax)
+}
+
+/* A square for a root that holds every point of the box [xmin, xmax] x [ymin, ymax].
+Its side is a power of two, at least 1 and more than twice the box's, and its corner
+is a multiple of half the side, so it is one of a grid of squares that are never
+straddled by the box. The side also has to be big enough for the corners and centre
+to come out exact: from 2^53 on, 1 added to a coordinate changes nothing. The result
+has infinite sides when no square will do. */
+fn square_around(xmin: f64, ymin: f64, xmax: f64, ymax: f64) -> RectHV {
+    let extent = (xmax - xmin).max(ymax - ymin);
+    let mut side: f64 = 1.0;
+    loop {
+        let half = side / 2.0;
+        let (x, y) = ((xmin / half).floor() * half, (ymin / half).floor() * half);
+        let square = RectHV::new(x, y, x + side, y + side);
+        let exact = |min: f64, max: f64| max - min == side && (min + half) - min == half;
+        if !side.is_finite()
+            || side > 2.0 * extent
+                && exact(square.xmin(), square.xmax())
+                && exact(square.ymin(), square.ymax())
+                && holds(&square, &Point2D::new(xmax, ymax))
+        {
+            return square;
+        }
+        side *= 2.0;
+    }
+}
+
+// are the sides finite, and so the corners and the centre?
+fn finite(rect: &RectHV) -> bool {
+    (rect.xmax() - rect.xmin()).is_finite() && (rect.ymax() - rect.ymin()).is_finite()
+}
+
+const TOO_BIG: &str = "the quadtree's square can't grow that big";
+
+// the root's square holds points on its west and south edges but not its east and
+// north ones, so that it can become a quadrant of a bigger square unchanged
+fn holds(rect: &RectHV, p: &Point2D) -> bool {
+    rect.xmin() <= p.x() && p.x() < rect.xmax() && rect.ymin() <= p.y() && p.y() < rect.ymax()
+}
+
+/* A point-region quadtree. Every node stands for a square: a leaf keeps up to capacity
+points, and a leaf that gets more is split into four quadrants, unless it is already
+max_depth levels down, where leaves just keep growing (so points that sit closer
+together than the resolution can't split forever). Unlike a 2d-tree, the shape
+depends only on which points are in it, not on the order they came in.
+
+The root's square starts as the unit cell around the first point (a bigger one for
+coordinates too big for a unit to register, see square_around) and doubles, with
+the old root becoming one of its quadrants, until it takes in any point that lands
+outside. Where the corners of the doubled square can't be exact any more, because
+the new point is much bigger than the old ones, the tree is built again around all
+of them instead. So coordinates of any size work, as long as a square with finite
+sides holds them all: a point at f64::MAX, or points that far apart, panic. */
+pub struct PRQuadTree {
+    root: Node,
+    // square of the root, None while the tree is empty
+    rect: Option<RectHV>,
+    size: usize,
+    capacity: usize,
+    max_depth: usize,
+}
+
+impl Default for PRQuadTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PRQuadTree {
+    // construct an empty quadtree with leaves of up to 4 points, at most 24 levels deep
+    pub fn new() -> Self {
+        Self {
+            root: Node::Leaf(vec![]),
+            rect: None,
+            size: 0,
+            capacity: 4,
+            max_depth: 24,
+        }
+    }
+
+    // the most points a leaf holds before it's split
+    pub fn with_node_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0);
+        self.capacity = capacity;
+        self
+    }
+
+    // the deepest level a leaf is split at (the root being level 0)
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // is the tree empty?
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    // number of points in the tree
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // number of levels of nodes (1 for a tree that is just a leaf)
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut stack = vec![(&self.root, 1)];
+        while let Some((node, depth)) = stack.pop() {
+            height = height.max(depth);
+            if let Node::Inner(children) = node {
+                stack.extend(children.iter().map(|c| (c, depth + 1)));
+            }
+        }
+        height
+    }
+
+    // the square of the root; None for an empty tree
+    pub fn bounds(&self) -> Option<&RectHV> {
+        self.rect.as_ref()
+    }
+
+    // add the point to the tree (if it is not already in the tree)
+    pub fn insert(&mut self, p: Point2D) {
+        assert!(p.x().is_finite() && p.y().is_finite());
+        let mut rect = match self.rect.take() {
+            Some(rect) if self.size > 0 => rect,
+            _ => {
+                self.root = Node::Leaf(vec![]);
+                square_around(p.x(), p.y(), p.x(), p.y())
+            }
+        };
+        assert!(finite(&rect), "{}", TOO_BIG);
+        while !holds(&rect, &p) {
+            // double towards p, the old root going in the opposite quadrant
+            let side = rect.xmax() - rect.xmin();
+            let (west, south) = (p.x() < rect.xmin(), p.y() < rect.ymin());
+            let xmin = if west {
+                rect.xmin() - side
+            } else {
+                rect.xmin()
+            };
+            let ymin = if south {
+                rect.ymin() - side
+            } else {
+                rect.ymin()
+            };
+            let q = west as usize | (south as usize) << 1;
+            let bigger = RectHV::new(xmin, ymin, xmin + 2.0 * side, ymin + 2.0 * side);
+            if !finite(&bigger) || quadrant_rect(&bigger, q) != rect {
+                self.rebuild(p);
+                return;
+            }
+            let mut children: Box<[Node; 4]> =
+                Box::new(std::array::from_fn(|_| Node::Leaf(vec![])));
+            children[q] = std::mem::replace(&mut self.root, Node::Leaf(vec![]));
+            self.root = Node::Inner(children);
+            rect = bigger;
+        }
+        if self.insert_at(p, &rect) {
+            self.size += 1;
+        }
+        self.rect = Some(rect);
+    }
+
+    // build the tree again, with p, in a square around all of the points
+    fn rebuild(&mut self, p: Point2D) {
+        let mut points = vec![p];
+        let mut stack = vec![std::mem::replace(&mut self.root, Node::Leaf(vec![]))];
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Leaf(leaf) => points.extend(leaf),
+                Node::Inner(children) => stack.extend(*children),
+            }
+        }
+        let (mut xmin, mut ymin) = (f64::INFINITY, f64::INFINITY);
+        let (mut xmax, mut ymax) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for q in points.iter() {
+            (xmin, xmax) = (xmin.min(q.x()), xmax.max(q.x()));
+            (ymin, ymax) = (ymin.min(q.y()), ymax.max(q.y()));
+        }
+        let rect = square_around(xmin, ymin, xmax, ymax);
+        assert!(finite(&rect), "{}", TOO_BIG);
+        self.size = 0;
+        for q in points {
+            if self.insert_at(q, &rect) {
+                self.size += 1;
+            }
+        }
+        self.rect = Some(rect);
+    }
+
+    // insert p below the root, whose square is rect; false if it was already there
+    fn insert_at(&mut self, p: Point2D, rect: &RectHV) -> bool {
+        let (capacity, max_depth) = (self.capacity, self.max_depth);
+        let mut node = &mut self.root;
+        let mut rect = rect.clone();
+        let mut depth = 0;
+        while let Node::Inner(children) = node {
+            let q = quadrant(&p, &rect);
+            rect = quadrant_rect(&rect, q);
+            node = &mut children[q];
+            depth += 1;
+        }
+        let Node::Leaf(points) = node else {
+            unreachable!()
+        };
+        if points.contains(&p) {
+            return false;
+        }
+        points.push(p);
+        /* Split an overfull leaf, and keep splitting the quadrant they went to if they
+        all went to the same one, until no leaf is over capacity or max_depth is hit. */
+        while depth < max_depth {
+            let Node::Leaf(points) = node else {
+                unreachable!()
+            };
+            if points.len() <= capacity {
+                break;
+            }
+            let mut children: [Vec<Point2D>; 4] = Default::default();
+            for p in points.drain(..) {
+                children[quadrant(&p, &rect)].push(p);
+            }
+            let full = children.iter().position(|c| c.len() > capacity);
+            *node = Node::Inner(Box::new(children.map(Node::Leaf)));
+            let Some(q) = full else {
+                break;
+            };
+            let Node::Inner(children) = node else {
+                unreachable!()
+            };
+            rect = quadrant_rect(&rect, q);
+            node = &mut children[q];
+            depth += 1;
+        }
+        true
+    }
+
+    // does the tree contain point p?
+    pub fn contains(&self, p: Point2D) -> bool {
+        let Some(rect) = self.rect.as_ref().filter(|r| holds(r, &p)) else {
+            return false;
+        };
+        let mut node = &self.root;
+        let mut rect = rect.clone();
+        loop {
+            match node {
+                Node::Leaf(points) => return points.contains(&p),
+                Node::Inner(children) => {
+                    let q = quadrant(&p, &rect);
+                    rect = quadrant_rect(&rect, q);
+                    node = &children[q];
+                }
+            }
+        }
+    }
+
+    // remove the point from the tree; false if it wasn't there
+    pub fn remove(&mut self, p: Point2D) -> bool {
+        let Some(rect) = self.rect.as_ref().filter(|r| holds(r, &p)) else {
+            return false;
+        };
+        let removed = Self::remove_at(&mut self.root, &p, rect.clone(), self.capacity);
+        if removed {
+            self.size -= 1;
+        }
+        removed
+    }
+
+    fn remove_at(node: &mut Node, p: &Point2D, rect: RectHV, capacity: usize) -> bool {
+        match node {
+            Node::Leaf(points) => match points.iter().position(|q| q == p) {
+                Some(i) => {
+                    points.swap_remove(i);
+                    true
+                }
+                None => false,
+            },
+            Node::Inner(children) => {
+                let q = quadrant(p, &rect);
+                if !Self::remove_at(&mut children[q], p, quadrant_rect(&rect, q), capacity) {
+                    return false;
+                }
+                // merge the quadrants back into a leaf once they'd fit in one
+                let mut count = 0;
+                for child in children.iter() {
+                    match child {
+                        Node::Leaf(points) => count += points.len(),
+                        Node::Inner(_) => return true,
+                    }
+                }
+                if count <= capacity {
+                    let mut points = Vec::with_capacity(count);
+                    for child in children.iter_mut() {
+                        if let Node::Leaf(child_points) = child {
+                            points.append(child_points);
+                        }
+                    }
+                    *node = Node::Leaf(points);
+                }
+                true
+            }
+        }
+    }
+
+    // every leaf with its square
+    fn leaves(&self) -> impl Iterator<Item = (&Vec<Point2D>, RectHV)> {
+        let mut stack: Vec<_> = self.rect.iter().map(|r| (&self.root, r.clone())).collect();
+        std::iter::from_fn(move || {
+            while let Some((node, rect)) = stack.pop() {
+                match node {
+                    Node::Leaf(points) => return Some((points, rect)),
+                    Node::Inner(children) => stack.extend(
+                        children
+                            .iter()
+                            .enumerate()
+                            .map(|(q, c)| (c, quadrant_rect(&rect, q))),
+                    ),
+                }
+            }
+            None
+        })
+    }
+
+    // draw all points to standard draw
+    pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        for (points, _) in self.leaves() {
+            for p in points.iter() {
+                p.draw(plot)?;
+            }
+        }
+        Ok(())
+    }
+
+    // draw the square of every leaf in red, then the points
+    pub fn draw_cells(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        plot.set_pen_color(RED);
+        for (_, rect) in self.leaves() {
+            rect.draw(plot)?;
+        }
+        plot.set_pen_color(BLUE);
+        // points last so the lines don't cover them
+        self.draw(plot)
+    }
+
+    // all points that are inside the region (or on the boundary)
+    pub fn range<R: Region>(&self, region: R) -> Vec<&Point2D> {
+        let mut found = vec![];
+        let mut stack: Vec<_> = self.rect.iter().map(|r| (&self.root, r.clone())).collect();
+        while let Some((node, rect)) = stack.pop() {
+            if !region.intersects(&rect) {
+                continue;
+            }
+            match node {
+                Node::Leaf(points) => found.extend(points.iter().filter(|p| region.contains(p))),
+                Node::Inner(children) => stack.extend(
+                    children
+                        .iter()
+                        .enumerate()
+                        .map(|(q, c)| (c, quadrant_rect(&rect, q))),
+                ),
+            }
+        }
+        found
+    }
+
+    // push the children of an inner node, nearest to p last so it's searched first
+    fn push_children<'a>(
+        stack: &mut Vec<(&'a Node, RectHV, f64)>,
+        children: &'a [Node; 4],
+        rect: &RectHV,
+        p: &Point2D,
+    ) {
+        let mut quadrants: Vec<_> = children
+            .iter()
+            .enumerate()
+            .map(|(q, c)| {
+                let r = quadrant_rect(rect, q);
+                let d = r.distance_squared_to(p);
+                (c, r, d)
+            })
+            .collect();
+        quadrants.sort_by(|a, b| b.2.total_cmp(&a.2));
+        stack.extend(quadrants);
+    }
+
+    // a nearest neighbor to point p (of those equally near, the smallest point);
+    // None if the tree is empty
+    pub fn nearest(&self, p: Point2D) -> Option<&Point2D> {
+        let mut best: Option<(f64, &Point2D)> = None;
+        let mut stack: Vec<_> = self
+            .rect
+            .iter()
+            .map(|r| (&self.root, r.clone(), 0.0))
+            .collect();
+        while let Some((node, rect, d)) = stack.pop() {
+            // equally distant squares are still searched, so ties go to the smaller point
+            if best.is_some_and(|(best_d, _)| d > best_d) {
+                continue;
+            }
+            match node {
+                Node::Leaf(points) => {
+                    for q in points.iter() {
+                        let d = q.distance_squared_to(&p);
+                        if best.is_none_or(|(best_d, b)| d < best_d || (d == best_d && q < b)) {
+                            best = Some((d, q));
+                        }
+                    }
+                }
+                Node::Inner(children) => Self::push_children(&mut stack, children, &rect, &p),
+            }
+        }
+        best.map(|(_, q)| q)
+    }
+
+    // the k points closest to p, nearest first (ties go to the smaller point)
+    pub fn nearest_k(&self, p: Point2D, k: usize) -> Vec<&Point2D> {
+        // as nearest, with a bounded max-heap of the best k for the best point
+        let mut heap: BinaryHeap<(FloatOrd<f64>, &Point2D)> = BinaryHeap::with_capacity(k + 1);
+        let mut stack = vec![];
+        if k > 0 {
+            stack.extend(self.rect.iter().map(|r| (&self.root, r.clone(), 0.0)));
+        }
+        while let Some((node, rect, d)) = stack.pop() {
+            if heap.len() == k && heap.peek().is_some_and(|worst| d > worst.0 .0) {
+                continue;
+            }
+            match node {
+                Node::Leaf(points) => {
+                    for q in points.iter() {
+                        let candidate = (FloatOrd(q.distance_squared_to(&p)), q);
+                        if heap.len() < k {
+                            heap.push(candidate);
+                        } else if heap.peek().is_some_and(|worst| candidate < *worst) {
+                            heap.pop();
+                            heap.push(candidate);
+                        }
+                    }
+                }
+                Node::Inner(children) => Self::push_children(&mut stack, children, &rect, &p),
+            }
+        }
+        heap.into_sorted_vec().into_iter().map(|(_, q)| q).collect()
+    }
+
+    // every point, in no particular order
+    pub fn all(&self) -> Vec<&Point2D> {
+        self.leaves()
+            .flat_map(|(points, _)| points.iter())
+            .collect()
+    }
+}
+
+impl PointIndex for PRQuadTree {
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn size(&self) -> usize {
+        self.size()
+    }
+
+    fn insert(&mut self, p: Point2D) {
+        self.insert(p)
+    }
+
+    fn remove(&mut self, p: Point2D) -> bool {
+        self.remove(p)
+    }
+
+    fn contains(&self, p: Point2D) -> bool {
+        self.contains(p)
+    }
+
+    fn range(&self, region: &dyn Region) -> Vec<&Point2D> {
+        self.range(region)
+    }
+
+    fn nearest(&self, p: Point2D) -> Option<&Point2D> {
+        self.nearest(p)
+    }
+
+    fn nearest_k(&self, p: Point2D, k: usize) -> Vec<&Point2D> {
+        self.nearest_k(p, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kdtrees::point_index::check_against_point_set;
+
+    #[test]
+    fn test_against_point_set() {
+        for seed in 0..10 {
+            for (capacity, max_depth) in [(1, 24), (4, 24), (16, 24), (2, 2)] {
+                check_against_point_set(
+                    |points| {
+                        let mut tree = PRQuadTree::new()
+                            .with_node_capacity(capacity)
+                            .with_max_depth(max_depth);
+                        points.into_iter().for_each(|p| tree.insert(p));
+                        tree
+                    },
+                    seed,
+                    1000,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_shape() {
+        let mut tree = PRQuadTree::new().with_node_capacity(1);
+        assert_eq!(tree.bounds(), None);
+        tree.insert(Point2D::new(0.1, 0.1));
+        assert_eq!(tree.bounds(), Some(&RectHV::new(0.0, 0.0, 1.0, 1.0)));
+        assert_eq!(tree.height(), 1);
+        // into the SW quadrant of the SW quadrant
+        tree.insert(Point2D::new(0.2, 0.2));
+        assert_eq!(tree.height(), 4);
+        // on the east edge of the root, so it doubles to the east and north
+        tree.insert(Point2D::new(1.0, 0.5));
+        assert_eq!(tree.bounds(), Some(&RectHV::new(0.0, 0.0, 2.0, 2.0)));
+        assert_eq!(tree.height(), 5);
+        // and to the west and south
+        tree.insert(Point2D::new(-0.5, -3.0));
+        assert_eq!(tree.bounds(), Some(&RectHV::new(-2.0, -6.0, 6.0, 2.0)));
+        assert_eq!(tree.size(), 4);
+
+        // removing merges quadrants back, until a lone leaf is left
+        tree.remove(Point2D::new(0.2, 0.2));
+        tree.remove(Point2D::new(1.0, 0.5));
+        tree.remove(Point2D::new(-0.5, -3.0));
+        assert_eq!(tree.height(), 1);
+        assert_eq!(tree.all(), vec![&Point2D::new(0.1, 0.1)]);
+
+        // the same point many times over is still one point
+        let mut tree = PRQuadTree::new().with_node_capacity(1).with_max_depth(3);
+        for _ in 0..10 {
+            tree.insert(Point2D::new(0.5, 0.5));
+        }
+        assert_eq!(tree.size(), 1);
+        // max_depth stops the splitting of points closer than the resolution
+        tree.insert(Point2D::new(0.5, 0.5 + 1e-9));
+        assert_eq!(tree.height(), 4);
+        assert_eq!(tree.size(), 2);
+    }
+
+    #[test]
+    fn test_large_coordinates() {
+        // from 2^53 on a unit cell is no wider than its corner
+        let mut tree = PRQuadTree::new().with_node_capacity(1);
+        let big = Point2D::new(1e16, 0.0);
+        tree.insert(big.clone());
+        assert_eq!(
+            tree.bounds(),
+            Some(&RectHV::new(1e16, 0.0, 1e16 + 4.0, 4.0))
+        );
+        let points = [
+            Point2D::new(1e16 + 2.0, 0.5),
+            Point2D::new(-1e300, 1e300),
+            Point2D::new(1e-300, -1e-300),
+            Point2D::new(f64::MAX / 4.0, -f64::MAX / 4.0),
+        ];
+        for p in points.iter() {
+            tree.insert(p.clone());
+        }
+        assert_eq!(tree.size(), 5);
+        assert!(tree.contains(big.clone()));
+        for p in points.iter() {
+            assert!(tree.contains(p.clone()));
+            assert_eq!(tree.nearest(p.clone()), Some(p));
+        }
+        assert_eq!(tree.nearest(Point2D::new(1e16, 1.0)), Some(&big));
+
+        // alone, a point of any size gets a square
+        for p in [
+            Point2D::new(f64::MAX / 2.0, 1.0),
+            Point2D::new(-f64::MAX, 0.0),
+        ] {
+            let mut tree = PRQuadTree::new();
+            tree.insert(p.clone());
+            assert!(tree.contains(p));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can't grow that big")]
+    fn test_too_large() {
+        let mut tree = PRQuadTree::new();
+        tree.insert(Point2D::new(-f64::MAX, 0.0));
+        tree.insert(Point2D::new(f64::MAX / 2.0, 0.0));
+    }
+}
@@ -0,0 +1,62 @@
+use super::Entries;
+
+// cut v into parts of as equal sizes as possible, in order
+fn chunks_evenly<X>(v: Vec<X>, parts: usize) -> impl Iterator<Item = Vec<X>> {
+    let mut left = v.len();
+    let mut it = v.into_iter();
+    (0..parts).rev().map(move |p| {
+        let size = left.div_ceil(p + 1);
+        left -= size;
+        it.by_ref().take(size).collect()
+    })
+}
+
+/* Sort-Tile-Recursive packing (Leutenegger, Lopez and Edgington) of one level of
+the tree: group the entries into nodes of at most max entries that overlap little.
+For P nodes, sort the entries by the x of their centers and cut them into sqrt(P)
+vertical slices, then sort each slice by y and cut it into nodes. The cuts are as
+even as possible, so no node is much less than half full. */
+pub(super) fn str_pack<X>(mut entries: Entries<X>, max: usize) -> Vec<Entries<X>> {
+    let nodes = entries.len().div_ceil(max);
+    let slices = (nodes as f64).sqrt().ceil() as usize;
+    entries.sort_by(|a, b| a.0.center().x().total_cmp(&b.0.center().x()));
+    let mut packed = Vec::with_capacity(nodes);
+    for mut slice in chunks_evenly(entries, slices) {
+        slice.sort_by(|a, b| a.0.center().y().total_cmp(&b.0.center().y()));
+        let parts = slice.len().div_ceil(max);
+        packed.extend(chunks_evenly(slice, parts));
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::rect_hv::RectHV;
+
+    #[test]
+    fn test_str_pack() {
+        // a 10 x 10 grid of unit squares, shuffled, into nodes of 25
+        let mut entries = vec![];
+        for i in 0..100 {
+            let (x, y) = ((i * 37 % 100 / 10) as f64, (i * 37 % 10) as f64);
+            entries.push((RectHV::new(x, y, x + 1.0, y + 1.0), i));
+        }
+        let nodes = str_pack(entries, 25);
+        assert_eq!(nodes.len(), 4);
+        // two slices of 50, each cut into two nodes of 5 x 5 squares
+        for node in nodes.iter() {
+            assert_eq!(node.len(), 25);
+            let bounds = node
+                .iter()
+                .skip(1)
+                .fold(node[0].0.clone(), |b, e| b.union(&e.0));
+            assert_eq!(bounds.area(), 25.0);
+        }
+        assert_eq!(
+            str_pack(vec![(RectHV::new(0.0, 0.0, 1.0, 1.0), 0)], 4).len(),
+            1
+        );
+        assert!(str_pack::<()>(vec![], 4).is_empty());
+    }
+}
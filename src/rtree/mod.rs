@@ -0,0 +1,567 @@
+// An R-tree: a spatial index of rectangles, each with a value, for data that are
+// boxes rather than points (see Guttman, "R-trees: a dynamic index structure for
+// spatial searching", 1984).
+//
+// Part 1: insert with quadratic split, and queries for the entries that intersect a
+//         rectangle, lie inside one, or contain one
+// Part 2: the entries nearest to a point, nearest first, by RectHV::distance_to
+// Part 3: delete, with the underfull nodes it leaves dissolved and their entries
+//         inserted again, and bulk loading with Sort-Tile-Recursive
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use float_ord::FloatOrd;
+use plotters::style::{BLUE, RED};
+
+use crate::util::{point2d::Point2D, rect_hv::RectHV, std_draw::Plot};
+
+use self::{bulk_load::str_pack, split::quadratic_split};
+
+mod bulk_load;
+mod split;
+
+// the entries of a node: rectangles with values at a leaf, with children above
+type Entries<X> = Vec<(RectHV, X)>;
+
+enum Node<T> {
+    Leaf(Entries<T>),
+    // each child with the smallest rectangle covering its entries
+    Inner(Entries<Node<T>>),
+}
+
+// the smallest rectangle covering the entries, of which there must be some
+fn cover<X>(entries: &[(RectHV, X)]) -> RectHV {
+    entries
+        .iter()
+        .skip(1)
+        .fold(entries[0].0.clone(), |r, e| r.union(&e.0))
+}
+
+impl<T> Node<T> {
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(entries) => entries.len(),
+            Node::Inner(children) => children.len(),
+        }
+    }
+
+    fn cover(&self) -> RectHV {
+        match self {
+            Node::Leaf(entries) => cover(entries),
+            Node::Inner(children) => cover(children),
+        }
+    }
+
+    // move every entry below this node into out
+    fn drain_into(self, out: &mut Entries<T>) {
+        match self {
+            Node::Leaf(entries) => out.extend(entries),
+            Node::Inner(children) => children.into_iter().for_each(|(_, c)| c.drain_into(out)),
+        }
+    }
+}
+
+// split the overfull entries of a node in two, keeping one half in place and
+// returning the other for the parent
+fn split<X>(entries: &mut Entries<X>, min: usize) -> Entries<X> {
+    let (a, b) = quadratic_split(std::mem::take(entries), min);
+    *entries = a;
+    b
+}
+
+pub struct RTree<T> {
+    root: Node<T>,
+    size: usize,
+    // entries per node, except at the root which may have fewer
+    max_entries: usize,
+    min_entries: usize,
+}
+
+impl<T> Default for RTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> RTree<T> {
+    // construct an empty R-tree with up to 16 entries per node
+    pub fn new() -> Self {
+        Self::default_with(16)
+    }
+
+    fn default_with(max_entries: usize) -> Self {
+        assert!(max_entries >= 2);
+        Self {
+            root: Node::Leaf(vec![]),
+            size: 0,
+            max_entries,
+            // 40% is what Beckmann et al. found to work best
+            min_entries: (max_entries * 2 / 5).max(1),
+        }
+    }
+
+    // the most entries a node holds before it's split
+    pub fn with_max_entries(self, max_entries: usize) -> Self {
+        assert!(self.is_empty());
+        Self::default_with(max_entries)
+    }
+
+    // construct an R-tree of the entries with Sort-Tile-Recursive bulk loading, which
+    // packs the nodes full and is much faster than inserting them one at a time
+    pub fn from_entries(entries: Vec<(RectHV, T)>) -> Self {
+        Self::new().bulk_load(entries)
+    }
+
+    // this (empty) tree's parameters, with the entries bulk loaded, see from_entries
+    pub fn bulk_load(mut self, entries: Vec<(RectHV, T)>) -> Self {
+        assert!(self.is_empty());
+        self.size = entries.len();
+        let max = self.max_entries;
+        let mut level: Vec<_> = str_pack(entries, max)
+            .into_iter()
+            .map(|e| (cover(&e), Node::Leaf(e)))
+            .collect();
+        while level.len() > 1 {
+            level = str_pack(level, max)
+                .into_iter()
+                .map(|c| (cover(&c), Node::Inner(c)))
+                .collect();
+        }
+        if let Some((_, root)) = level.pop() {
+            self.root = root;
+        }
+        self
+    }
+
+    // is the tree empty?
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    // number of entries in the tree
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // number of levels of nodes; every leaf is on the last one
+    pub fn height(&self) -> usize {
+        let mut height = 1;
+        let mut node = &self.root;
+        while let Node::Inner(children) = node {
+            height += 1;
+            node = &children[0].1;
+        }
+        height
+    }
+
+    // the smallest rectangle covering every entry; None for an empty tree
+    pub fn bounds(&self) -> Option<RectHV> {
+        (!self.is_empty()).then(|| self.root.cover())
+    }
+
+    // add an entry to the tree (entries may repeat)
+    pub fn insert(&mut self, rect: RectHV, value: T) {
+        self.size += 1;
+        self.reinsert(rect, value);
+    }
+
+    fn reinsert(&mut self, rect: RectHV, value: T) {
+        let (max, min) = (self.max_entries, self.min_entries);
+        if let Some(sibling) = Self::insert_at(&mut self.root, rect, value, max, min) {
+            // the root was split, so the tree grows a level
+            let old = std::mem::replace(&mut self.root, Node::Leaf(vec![]));
+            self.root = Node::Inner(vec![(old.cover(), old), sibling]);
+        }
+    }
+
+    // add the entry below node; returns the new sibling if node had to be split
+    fn insert_at(
+        node: &mut Node<T>,
+        rect: RectHV,
+        value: T,
+        max: usize,
+        min: usize,
+    ) -> Option<(RectHV, Node<T>)> {
+        match node {
+            Node::Leaf(entries) => {
+                entries.push((rect, value));
+                if entries.len() <= max {
+                    return None;
+                }
+                let other = split(entries, min);
+                Some((cover(&other), Node::Leaf(other)))
+            }
+            Node::Inner(children) => {
+                // the child that has to grow least to take it, else the smallest
+                let (i, _) = children
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, (r, _))| (FloatOrd(r.enlargement(&rect)), FloatOrd(r.area())))
+                    .unwrap();
+                children[i].0 = children[i].0.union(&rect);
+                let sibling = Self::insert_at(&mut children[i].1, rect, value, max, min)?;
+                children[i].0 = children[i].1.cover();
+                children.push(sibling);
+                if children.len() <= max {
+                    return None;
+                }
+                let other = split(children, min);
+                Some((cover(&other), Node::Inner(other)))
+            }
+        }
+    }
+
+    // remove an entry equal to (rect, value); false if there is none
+    pub fn remove(&mut self, rect: &RectHV, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        let mut orphans = vec![];
+        if !Self::remove_at(&mut self.root, rect, value, self.min_entries, &mut orphans) {
+            return false;
+        }
+        self.size -= 1;
+        loop {
+            match &mut self.root {
+                Node::Inner(children) if children.len() == 1 => {
+                    self.root = children.pop().unwrap().1;
+                }
+                Node::Inner(children) if children.is_empty() => self.root = Node::Leaf(vec![]),
+                _ => break,
+            }
+        }
+        for (rect, value) in orphans {
+            self.reinsert(rect, value);
+        }
+        true
+    }
+
+    fn remove_at(
+        node: &mut Node<T>,
+        rect: &RectHV,
+        value: &T,
+        min: usize,
+        orphans: &mut Entries<T>,
+    ) -> bool
+    where
+        T: PartialEq,
+    {
+        /* Guttman's CondenseTree: a child left with fewer than min entries is taken
+        out of the tree, and the caller inserts its entries again, so nodes stay at least
+        min full and the rectangles tight. Guttman reinserts a dissolved inner node's
+        subtrees whole, at their own level; this reinserts the entries below them one
+        at a time, which is simpler and just as correct. */
+        match node {
+            Node::Leaf(entries) => {
+                match entries.iter().position(|(r, v)| r == rect && v == value) {
+                    Some(i) => {
+                        entries.swap_remove(i);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Node::Inner(children) => {
+                for i in 0..children.len() {
+                    if !children[i].0.contains_rect(rect)
+                        || !Self::remove_at(&mut children[i].1, rect, value, min, orphans)
+                    {
+                        continue;
+                    }
+                    if children[i].1.len() < min {
+                        children.swap_remove(i).1.drain_into(orphans);
+                    } else {
+                        children[i].0 = children[i].1.cover();
+                    }
+                    return true;
+                }
+                false
+            }
+        }
+    }
+
+    // the entries whose rectangles pass keep, looking only below the nodes whose
+    // rectangles pass descend
+    fn search(
+        &self,
+        descend: impl Fn(&RectHV) -> bool,
+        keep: impl Fn(&RectHV) -> bool,
+    ) -> Vec<(&RectHV, &T)> {
+        let mut found = vec![];
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Leaf(entries) => {
+                    found.extend(entries.iter().filter(|(r, _)| keep(r)).map(|(r, v)| (r, v)))
+                }
+                Node::Inner(children) => {
+                    stack.extend(children.iter().filter(|(r, _)| descend(r)).map(|(_, c)| c))
+                }
+            }
+        }
+        found
+    }
+
+    // all entries whose rectangles intersect rect (boundaries included), in any order
+    pub fn intersecting(&self, rect: &RectHV) -> Vec<(&RectHV, &T)> {
+        self.search(|r| r.intersects(rect), |r| r.intersects(rect))
+    }
+
+    // all entries whose rectangles lie inside rect, in any order
+    pub fn contained_in(&self, rect: &RectHV) -> Vec<(&RectHV, &T)> {
+        self.search(|r| r.intersects(rect), |r| rect.contains_rect(r))
+    }
+
+    // all entries whose rectangles contain rect (a point being a rectangle with no
+    // area), in any order
+    pub fn containing(&self, rect: &RectHV) -> Vec<(&RectHV, &T)> {
+        self.search(|r| r.contains_rect(rect), |r| r.contains_rect(rect))
+    }
+
+    // all entries, in order of their rectangles' distance to p, nearest first
+    pub fn nearest_iter(&self, p: Point2D) -> NearestIter<'_, T> {
+        let mut heap = BinaryHeap::new();
+        if !self.is_empty() {
+            heap.push(Queued {
+                distance: 0.0,
+                item: Item::Node(&self.root),
+            });
+        }
+        NearestIter { p, heap }
+    }
+
+    // an entry whose rectangle is nearest to p (distance 0 if it contains p); None
+    // if the tree is empty
+    pub fn nearest(&self, p: Point2D) -> Option<(&RectHV, &T)> {
+        self.nearest_iter(p).next()
+    }
+
+    // the k entries whose rectangles are nearest to p, nearest first
+    pub fn nearest_k(&self, p: Point2D, k: usize) -> Vec<(&RectHV, &T)> {
+        self.nearest_iter(p).take(k).collect()
+    }
+
+    // every entry, in no particular order
+    pub fn all(&self) -> Vec<(&RectHV, &T)> {
+        self.search(|_| true, |_| true)
+    }
+
+    // draw the rectangle of every entry to standard draw
+    pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        for (r, _) in self.all() {
+            r.draw(plot)?;
+        }
+        Ok(())
+    }
+
+    // draw the rectangles of the inner nodes' children in red, then the entries
+    pub fn draw_nodes(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        plot.set_pen_color(RED);
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            if let Node::Inner(children) = node {
+                for (r, c) in children.iter() {
+                    r.draw(plot)?;
+                    stack.push(c);
+                }
+            }
+        }
+        plot.set_pen_color(BLUE);
+        self.draw(plot)
+    }
+}
+
+enum Item<'a, T> {
+    Node(&'a Node<T>),
+    Entry(&'a RectHV, &'a T),
+}
+
+// a node or entry waiting in the nearest search; the heap pops the nearest first
+struct Queued<'a, T> {
+    distance: f64,
+    item: Item<'a, T>,
+}
+
+impl<T> Ord for Queued<'_, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, BinaryHeap is a max-heap
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl<T> PartialOrd for Queued<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for Queued<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Queued<'_, T> {}
+
+/* Best-first search (Hjaltason and Samet): one heap holds both nodes, keyed by the
+distance to their rectangles, and entries, keyed by their own. A node's rectangle is
+never further than anything below it, so whenever an entry comes off the heap nothing
+left can be nearer. Each next() only opens as many nodes as it has to. */
+pub struct NearestIter<'a, T> {
+    p: Point2D,
+    heap: BinaryHeap<Queued<'a, T>>,
+}
+
+impl<'a, T> Iterator for NearestIter<'a, T> {
+    type Item = (&'a RectHV, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(Queued { item, .. }) = self.heap.pop() {
+            match item {
+                Item::Entry(r, v) => return Some((r, v)),
+                Item::Node(Node::Leaf(entries)) => {
+                    self.heap.extend(entries.iter().map(|(r, v)| Queued {
+                        distance: r.distance_squared_to(&self.p),
+                        item: Item::Entry(r, v),
+                    }))
+                }
+                Item::Node(Node::Inner(children)) => {
+                    self.heap.extend(children.iter().map(|(r, c)| Queued {
+                        distance: r.distance_squared_to(&self.p),
+                        item: Item::Node(c),
+                    }))
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_rect(rng: &mut StdRng) -> RectHV {
+        let (x, y) = (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0));
+        // mostly small boxes, some points
+        let (w, h) = if rng.gen_bool(0.1) {
+            (0.0, 0.0)
+        } else {
+            (rng.gen_range(0.0..0.1), rng.gen_range(0.0..0.1))
+        };
+        RectHV::new(x, y, x + w, y + h)
+    }
+
+    // every leaf on the same level, every rectangle the cover of its child and every
+    // node but the root between min and max full
+    fn check(tree: &RTree<usize>) {
+        let mut stack = vec![(&tree.root, 1)];
+        while let Some((node, depth)) = stack.pop() {
+            if depth > 1 {
+                assert!(node.len() >= tree.min_entries);
+            }
+            assert!(node.len() <= tree.max_entries);
+            match node {
+                Node::Leaf(_) => assert_eq!(depth, tree.height()),
+                Node::Inner(children) => {
+                    for (r, c) in children.iter() {
+                        assert_eq!(*r, c.cover());
+                        stack.push((c, depth + 1));
+                    }
+                }
+            }
+        }
+    }
+
+    fn sorted(v: Vec<(&RectHV, &usize)>) -> Vec<usize> {
+        let mut ids: Vec<usize> = v.into_iter().map(|(_, v)| *v).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_against_brute_force() {
+        let mut rng = StdRng::seed_from_u64(48);
+        for max_entries in [2, 4, 16] {
+            let initial: Vec<(RectHV, usize)> =
+                (0..500).map(|i| (random_rect(&mut rng), i)).collect();
+            let mut tree = RTree::new().with_max_entries(max_entries);
+            for (r, v) in initial.iter() {
+                tree.insert(r.clone(), *v);
+            }
+            check(&tree);
+            // the bulk loaded tree keeps them all, the other loses some as it goes
+            let bulk = RTree::new()
+                .with_max_entries(max_entries)
+                .bulk_load(initial.clone());
+            check(&bulk);
+            let mut entries = initial.clone();
+
+            for step in 0..400 {
+                if step % 2 == 0 {
+                    // remove one that is there and try one that isn't
+                    let (r, v) = entries.swap_remove(rng.gen_range(0..entries.len()));
+                    assert!(tree.remove(&r, &v));
+                    assert!(!tree.remove(&r, &v));
+                    check(&tree);
+                }
+                assert_eq!(tree.size(), entries.len());
+                let q = random_rect(&mut rng);
+                for (t, expected) in [(&tree, &entries), (&bulk, &initial)] {
+                    let brute = |f: &dyn Fn(&RectHV) -> bool| {
+                        let mut ids: Vec<usize> =
+                            expected.iter().filter(|(r, _)| f(r)).map(|e| e.1).collect();
+                        ids.sort();
+                        ids
+                    };
+                    assert_eq!(sorted(t.intersecting(&q)), brute(&|r| r.intersects(&q)));
+                    assert_eq!(sorted(t.contained_in(&q)), brute(&|r| q.contains_rect(r)));
+                    let p = RectHV::new(q.xmin(), q.ymin(), q.xmin(), q.ymin());
+                    assert_eq!(sorted(t.containing(&p)), brute(&|r| r.contains_rect(&p)));
+                }
+                let p = q.center();
+                let mut distances: Vec<f64> =
+                    entries.iter().map(|(r, _)| r.distance_to(&p)).collect();
+                distances.sort_by(f64::total_cmp);
+                let got: Vec<f64> = tree
+                    .nearest_k(p.clone(), 5)
+                    .iter()
+                    .map(|(r, _)| r.distance_to(&p))
+                    .collect();
+                assert_eq!(got, distances[..5]);
+            }
+            // and the rest, down to an empty tree
+            for (r, v) in entries.drain(..) {
+                assert!(tree.remove(&r, &v));
+            }
+            check(&tree);
+            assert!(tree.is_empty());
+            assert_eq!(tree.height(), 1);
+            assert_eq!(tree.nearest(Point2D::new(0.5, 0.5)), None);
+        }
+    }
+
+    #[test]
+    fn test_bulk_load() {
+        let mut rng = StdRng::seed_from_u64(49);
+        let entries: Vec<(RectHV, usize)> =
+            (0..10_000).map(|i| (random_rect(&mut rng), i)).collect();
+        let tree = RTree::from_entries(entries.clone());
+        check(&tree);
+        assert_eq!(tree.size(), 10_000);
+        // 625 full leaves, 42 nodes above them, then 4 and the root
+        assert_eq!(tree.height(), 4);
+        assert_eq!(sorted(tree.all()), (0..10_000).collect::<Vec<_>>());
+        let bounds = entries
+            .iter()
+            .skip(1)
+            .fold(entries[0].0.clone(), |b, e| b.union(&e.0));
+        assert_eq!(tree.bounds(), Some(bounds));
+
+        let empty = RTree::<usize>::from_entries(vec![]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.bounds(), None);
+        assert_eq!(empty.all(), vec![]);
+    }
+}
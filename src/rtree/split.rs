@@ -0,0 +1,97 @@
+use float_ord::FloatOrd;
+
+use crate::util::rect_hv::RectHV;
+
+use super::Entries;
+
+/* Guttman's quadratic split of an overfull node's entries into two groups of at least
+min entries each. The seeds are the pair that would waste the most area if they
+shared a rectangle. Then the remaining entry with the strongest preference for one
+group goes to it, over and over, where preference is the difference between the two
+enlargements. An entry goes to the group it enlarges least, else the smaller one,
+else the one with fewer entries. Once a group needs every remaining entry to reach
+min, it gets them all. */
+pub(super) fn quadratic_split<X>(entries: Entries<X>, min: usize) -> (Entries<X>, Entries<X>) {
+    assert!(entries.len() >= 2 && entries.len() >= 2 * min);
+    let (s1, s2) = pick_seeds(&entries);
+    let mut rest: Vec<Option<(RectHV, X)>> = entries.into_iter().map(Some).collect();
+    let (first, second) = (rest[s1].take().unwrap(), rest[s2].take().unwrap());
+    let mut bounds = [first.0.clone(), second.0.clone()];
+    let mut groups = [vec![first], vec![second]];
+    let mut remaining: Vec<usize> = (0..rest.len()).filter(|i| rest[*i].is_some()).collect();
+    while !remaining.is_empty() {
+        for group in groups.iter_mut() {
+            if group.len() + remaining.len() == min {
+                group.extend(remaining.drain(..).map(|i| rest[i].take().unwrap()));
+            }
+        }
+        // pick next
+        let Some((k, _)) = remaining.iter().enumerate().max_by_key(|(_, i)| {
+            let r = &rest[**i].as_ref().unwrap().0;
+            FloatOrd((bounds[0].enlargement(r) - bounds[1].enlargement(r)).abs())
+        }) else {
+            break;
+        };
+        let entry = rest[remaining.swap_remove(k)].take().unwrap();
+        let key = |g: usize| {
+            (
+                FloatOrd(bounds[g].enlargement(&entry.0)),
+                FloatOrd(bounds[g].area()),
+                groups[g].len(),
+            )
+        };
+        let g = if key(0) <= key(1) { 0 } else { 1 };
+        bounds[g] = bounds[g].union(&entry.0);
+        groups[g].push(entry);
+    }
+    let [a, b] = groups;
+    (a, b)
+}
+
+// the two entries whose covering rectangle has the most area that neither covers
+fn pick_seeds<X>(entries: &[(RectHV, X)]) -> (usize, usize) {
+    let mut seeds = (0, 1);
+    let mut worst = f64::NEG_INFINITY;
+    for i in 0..entries.len() {
+        for j in i + 1..entries.len() {
+            let (a, b) = (&entries[i].0, &entries[j].0);
+            let waste = a.union(b).area() - a.area() - b.area();
+            if waste > worst {
+                worst = waste;
+                seeds = (i, j);
+            }
+        }
+    }
+    seeds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quadratic_split() {
+        // two clusters of small squares, and one big rectangle
+        let mut entries = vec![];
+        for i in 0..4 {
+            let d = i as f64 * 0.01;
+            entries.push((RectHV::new(d, d, d + 0.1, d + 0.1), i));
+            entries.push((RectHV::new(5.0 + d, 5.0, 5.1 + d, 5.1), 10 + i));
+        }
+        let (a, b) = quadratic_split(entries.clone(), 2);
+        let ids = |g: &Vec<(RectHV, i32)>| {
+            let mut ids: Vec<i32> = g.iter().map(|e| e.1).collect();
+            ids.sort();
+            ids
+        };
+        let mut groups = [ids(&a), ids(&b)];
+        groups.sort();
+        assert_eq!(groups, [vec![0, 1, 2, 3], vec![10, 11, 12, 13]]);
+
+        // however lopsided, both groups get min entries
+        entries.push((RectHV::new(100.0, 100.0, 101.0, 101.0), 99));
+        let (a, b) = quadratic_split(entries, 4);
+        assert_eq!(a.len().min(b.len()), 4);
+        assert_eq!(a.len() + b.len(), 9);
+    }
+}
@@ -0,0 +1,194 @@
+// Primality and factorization for any u64, for numbers too big to sieve.
+//
+// Miller–Rabin with the first 12 primes as bases is deterministic for every
+// n < 3.3 * 10^24, which covers all of u64. Composites are split with Brent's
+// variant of Pollard's rho. Products are formed in u128 so nothing overflows.
+
+const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    ((a as u128 * b as u128) % m as u128) as u64
+}
+
+fn pow_mod(mut b: u64, mut e: u64, m: u64) -> u64 {
+    let mut result = 1;
+    b %= m;
+    while e > 0 {
+        if e & 1 == 1 {
+            result = mul_mod(result, b, m);
+        }
+        b = mul_mod(b, b, m);
+        e >>= 1;
+    }
+    result
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Deterministic Miller–Rabin: https://en.wikipedia.org/wiki/Miller%E2%80%93Rabin_primality_test
+pub fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in BASES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    // n - 1 = d * 2^s with d odd
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    'witness: for a in BASES {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+// Brent's cycle finding on x -> x^2 + c mod n. Returns a non-trivial factor of
+// the composite n, or None if this c only found n itself.
+fn brent(n: u64, c: u64) -> Option<u64> {
+    const M: u64 = 128;
+    let f = |x: u64| ((mul_mod(x, x, n) as u128 + c as u128) % n as u128) as u64;
+    let (mut x, mut y, mut ys) = (0, 2, 2);
+    let (mut r, mut q, mut g) = (1, 1, 1);
+    while g == 1 {
+        x = y;
+        for _ in 0..r {
+            y = f(y);
+        }
+        let mut k = 0;
+        while k < r && g == 1 {
+            ys = y;
+            // batch the gcds: multiply M differences together and take one gcd
+            for _ in 0..M.min(r - k) {
+                y = f(y);
+                q = mul_mod(q, x.abs_diff(y), n);
+            }
+            g = gcd(q, n);
+            k += M;
+        }
+        r <<= 1;
+    }
+    if g == n {
+        // the batch overshot, step back through it one gcd at a time
+        loop {
+            ys = f(ys);
+            g = gcd(x.abs_diff(ys), n);
+            if g > 1 {
+                break;
+            }
+        }
+    }
+    (g != n).then_some(g)
+}
+
+fn split(n: u64, factors: &mut Vec<u64>) {
+    if n == 1 {
+        return;
+    }
+    if is_prime(n) {
+        factors.push(n);
+        return;
+    }
+    let d = (1..)
+        .find_map(|c| brent(n, c))
+        .expect("rho always finds a factor of a composite");
+    split(d, factors);
+    split(n / d, factors);
+}
+
+/// Prime factorization of n as (prime, exponent) pairs in increasing order of prime.
+/// factorize(1) is empty.
+pub fn factorize(n: u64) -> Vec<(u64, u32)> {
+    assert!(n > 0);
+    let mut n = n;
+    let mut factors = vec![];
+    // trial division gets the small factors out of the way cheaply
+    let mut d = 2;
+    while d < 100 && d * d <= n {
+        while n.is_multiple_of(d) {
+            factors.push(d);
+            n /= d;
+        }
+        d += if d == 2 { 1 } else { 2 };
+    }
+    split(n, &mut factors);
+    factors.sort_unstable();
+
+    let mut result: Vec<(u64, u32)> = vec![];
+    for p in factors {
+        match result.last_mut() {
+            Some((q, e)) if *q == p => *e += 1,
+            _ => result.push((p, 1)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sieve::PrimeBits;
+
+    #[test]
+    fn test_is_prime() {
+        let p = PrimeBits::new(100_000);
+        for n in 0..100_000u64 {
+            assert_eq!(is_prime(n), p.is_prime(n as usize).unwrap(), "{n}");
+        }
+        // strong pseudoprimes to the smaller bases
+        for n in [2047, 3_215_031_751, 3_825_123_056_546_413_051] {
+            assert!(!is_prime(n));
+        }
+        assert!(is_prime((1 << 61) - 1));
+        assert!(is_prime(18_446_744_073_709_551_557)); // largest u64 prime
+        assert!(!is_prime(u64::MAX));
+    }
+
+    #[test]
+    fn test_factorize() {
+        assert_eq!(factorize(1), vec![]);
+        assert_eq!(factorize(2), vec![(2, 1)]);
+        assert_eq!(factorize(360), vec![(2, 3), (3, 2), (5, 1)]);
+        assert_eq!(
+            factorize(600_851_475_143),
+            vec![(71, 1), (839, 1), (1471, 1), (6857, 1)]
+        );
+        assert_eq!(
+            factorize(u64::MAX),
+            vec![
+                (3, 1),
+                (5, 1),
+                (17, 1),
+                (257, 1),
+                (641, 1),
+                (65537, 1),
+                (6_700_417, 1)
+            ]
+        );
+        // two 32-bit primes
+        let (a, b) = (4_294_967_279, 4_294_967_291);
+        assert_eq!(factorize(a * b), vec![(a, 1), (b, 1)]);
+        assert_eq!(factorize(a * a), vec![(a, 2)]);
+
+        for n in 1..5000u64 {
+            let product: u64 = factorize(n).iter().map(|(p, e)| p.pow(*e)).product();
+            assert_eq!(product, n);
+        }
+    }
+}
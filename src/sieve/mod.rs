@@ -0,0 +1,180 @@
+/*
+Create a data structure with an array [0u64; 1024]. This is a bit vector which has 65,536 elements, all of them zero (initially).
+1. Set the i^th bit to 1 if and only if i is prime.
+2. Add a method fn is_prime(n: usize) -> Option<bool>. If n >= 65536 you may return None.
+   Otherwise, return true/false whether n is prime or not. You may not use divison, mod or multiplication in your solution.
+3. Count the number of primes < 65536 using your data structure.
+4. Write a routine that extracts n bits starting at bit m into a u64. You may assume that n<=64 and n>0 and m+n<=65536.
+   You may not assume anything else about n and m.
+
+The table is now sized at runtime and only stores odd numbers: bit i stands for 2i+1.
+Even numbers are answered without looking at the table, which halves the memory
+(a limit of 10^9 takes 62.5MB).
+*/
+
+pub mod factor;
+pub mod multiplicative;
+pub mod segmented;
+
+use crate::util::bit_vec::BitVec;
+
+pub struct PrimeBits {
+    limit: usize,
+    primes: BitVec,
+}
+
+impl PrimeBits {
+    /// The table covers every n < limit.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Return true/false whether n is prime or not, or None if n is beyond the table.
+    // You may not use divison, mod or multiplication in your solution.
+    pub fn is_prime(&self, n: usize) -> Option<bool> {
+        if n >= self.limit {
+            None
+        } else if n & 1 == 0 {
+            Some(n == 2)
+        } else {
+            Some(self.primes.is_set(n >> 1))
+        }
+    }
+
+    /// Primality for any u64: a table lookup below the limit, deterministic
+    /// Miller–Rabin above it.
+    pub fn is_prime_u64(&self, n: u64) -> bool {
+        match usize::try_from(n).ok().and_then(|n| self.is_prime(n)) {
+            Some(b) => b,
+            None => factor::is_prime(n),
+        }
+    }
+
+    /// number of primes
+    pub fn num_primes(&self) -> usize {
+        // 2 is the only prime not in the table
+        self.primes.count_ones(0..self.primes.len()) + (self.limit > 2) as usize
+    }
+
+    /// π(x): number of primes <= x, or None if x is beyond the table.
+    pub fn prime_pi(&self, x: usize) -> Option<usize> {
+        if x >= self.limit {
+            None
+        } else {
+            // odd primes p = 2i+1 <= x are the set bits below (x+1)/2
+            Some(self.primes.rank1((x + 1) >> 1) + (x >= 2) as usize)
+        }
+    }
+
+    /// The n-th prime, counting from nth_prime(1) = 2, or None if it is beyond the table.
+    pub fn nth_prime(&self, n: usize) -> Option<usize> {
+        match n {
+            0 => None,
+            1 => (self.limit > 2).then_some(2),
+            _ => self.primes.select1(n - 2).map(|i| (i << 1) + 1),
+        }
+    }
+
+    /// All the primes below the limit, in order.
+    pub fn primes(&self) -> impl Iterator<Item = usize> + '_ {
+        let two = (self.limit > 2).then_some(2);
+        two.into_iter()
+            .chain(self.primes.iter_ones().map(|i| (i << 1) + 1))
+    }
+
+    /// extracts n bits starting at bit m into a u64
+    // Bits are those of the odd-only table, i.e. bit i says whether 2i+1 is prime.
+    pub fn extract(&self, m: u64, n: u64) -> u64 {
+        self.primes.extract(m, n)
+    }
+
+    /// Sieve of Eratosthenes: https://en.wikipedia.org/wiki/Sieve_of_Eratosthenes
+    /// over the odd numbers below `limit`.
+    pub fn new(limit: usize) -> Self {
+        let nbits = limit >> 1;
+        let mut primes = BitVec::ones(nbits);
+        if nbits > 0 {
+            primes.clear(0); // 1 is not prime
+        }
+        let mut i = 1;
+        loop {
+            let n = (i << 1) + 1;
+            let mut j = (n * n) >> 1;
+            if j >= nbits {
+                break;
+            }
+            if primes.is_set(i) {
+                // odd multiples of n are n apart in the table
+                while j < nbits {
+                    primes.clear(j);
+                    j += n;
+                }
+            }
+            i += 1;
+        }
+        primes.build_index();
+        PrimeBits { limit, primes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let p = PrimeBits::new(65536);
+        assert_eq!(p.is_prime(199), Some(true));
+        assert_eq!(p.is_prime(198), Some(false));
+        assert_eq!(p.is_prime(65536), None);
+        assert_eq!(p.num_primes(), 6542);
+        // bits 60..68 are 121, 123, ... 135 of which 127 and 131 are prime
+        assert_eq!(p.extract(60, 8), 0b0010_1000);
+        assert_eq!(p.extract(0, 64), p.primes.words()[0]);
+        assert_eq!(p.extract(64, 64), p.primes.words()[1]);
+        // println!("{:064b}", p.primes.words()[0]);
+        // println!("{:064b}", p.primes.words()[1]);
+        // println!("{:064b}", p.extract(60, 64));
+    }
+
+    #[test]
+    fn test_limits() {
+        for limit in 0..200 {
+            let p = PrimeBits::new(limit);
+            let naive: Vec<usize> = (2..limit).filter(|n| (2..*n).all(|d| n % d != 0)).collect();
+            assert_eq!(p.primes().collect::<Vec<_>>(), naive);
+            assert_eq!(p.num_primes(), naive.len());
+            for n in 0..limit {
+                assert_eq!(p.is_prime(n), Some(naive.contains(&n)));
+            }
+            assert_eq!(p.is_prime(limit), None);
+            for (k, q) in naive.iter().enumerate() {
+                assert_eq!(p.nth_prime(k + 1), Some(*q));
+            }
+            for x in 0..limit {
+                let pi = naive.iter().filter(|q| **q <= x).count();
+                assert_eq!(p.prime_pi(x), Some(pi));
+            }
+        }
+        let p = PrimeBits::new(1_000_000);
+        assert_eq!(p.num_primes(), 78498);
+        assert_eq!(p.is_prime(65537), Some(true));
+        assert_eq!(p.is_prime(999_983), Some(true));
+        assert_eq!(p.prime_pi(999_999), Some(78498));
+        assert_eq!(p.nth_prime(78498), Some(999_983));
+        assert_eq!(p.nth_prime(78499), None);
+        assert!(p.is_prime_u64(999_983));
+        assert!(p.is_prime_u64(1_000_000_000_039));
+        assert!(!p.is_prime_u64(1_000_000_000_041));
+    }
+
+    #[test]
+    fn test2() {
+        let p = PrimeBits::new(65536);
+        for i in 0..128 {
+            for j in 1..=64 {
+                println!("{:2},{:2} {:064b}", i, j, p.extract(i, j));
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+// Linear sieves (Gries–Misra) for tables of arithmetic functions below a limit.
+//
+// Every composite n is reached exactly once, as n = p * i where p is the smallest
+// prime factor of n, so each table is filled in O(limit). Tables cover every
+// n < limit and values are u32, so limits go up to 2^32. Memory per n: the smallest
+// prime factor table stores odd numbers only, 2 bytes; totients 4 bytes, mobius 1,
+// divisor_counts 5 (counts and exponents), each plus a bit to mark composites
+// while sieving. Only the primes below sqrt(limit) are kept, see linear_sieve.
+use crate::util::bit_vec::BitVec;
+
+// Calls visit(n, i, p) once for every 1 < n < limit, in increasing order of n,
+// where p is the smallest prime factor of n and n = p * i. For a prime n that is
+// visit(n, 1, n). Since i < n, its table entry has always been filled in already.
+//
+// A prime p only multiplies i >= p, so p * p < limit for every p that gets used:
+// the list of primes stops at sqrt(limit), and fits in u32.
+fn linear_sieve(limit: usize, mut visit: impl FnMut(usize, usize, usize)) {
+    let mut is_composite = BitVec::new(limit);
+    let mut primes: Vec<u32> = vec![];
+    for i in 2..limit {
+        if !is_composite.is_set(i) {
+            if i * i < limit {
+                primes.push(i as u32);
+            }
+            visit(i, 1, i);
+        }
+        for p in primes.iter().map(|p| *p as usize) {
+            let n = p * i;
+            if n >= limit {
+                break;
+            }
+            is_composite.set(n);
+            visit(n, i, p);
+            if i.is_multiple_of(p) {
+                // a bigger p would not be the smallest factor of p * i
+                break;
+            }
+        }
+    }
+}
+
+fn check_limit(limit: usize) {
+    assert!(limit as u64 <= 1 << 32, "tables hold u32 values");
+}
+
+/// Smallest prime factor of every n < limit, for O(log n) factorization.
+pub struct SmallestPrimeFactors {
+    limit: usize,
+    // spf[i] is the smallest prime factor of 2i+1, or 0 when 2i+1 is 1 or prime
+    spf: Vec<u32>,
+}
+
+impl SmallestPrimeFactors {
+    pub fn new(limit: usize) -> Self {
+        check_limit(limit);
+        let nbits = limit >> 1;
+        let mut spf = vec![0u32; nbits];
+        // as in linear_sieve, only primes below sqrt(limit) are ever used
+        let mut primes: Vec<u32> = vec![];
+        // odd composites only have odd factors, so the linear sieve can skip evens
+        for i in (3..limit).step_by(2) {
+            let smallest = match spf[i >> 1] {
+                0 => {
+                    if i * i < limit {
+                        primes.push(i as u32);
+                    }
+                    i
+                }
+                p => p as usize,
+            };
+            for p in primes.iter().map(|p| *p as usize) {
+                let n = p * i;
+                if p > smallest || n >= limit {
+                    break;
+                }
+                // only stored for composites, where it is at most sqrt(n) < 2^32
+                spf[n >> 1] = p as u32;
+            }
+        }
+        Self { limit, spf }
+    }
+
+    /// The table covers every n < limit.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The smallest prime factor of n, or None if n < 2 or n is beyond the table.
+    pub fn get(&self, n: usize) -> Option<usize> {
+        if n < 2 || n >= self.limit {
+            None
+        } else if n & 1 == 0 {
+            Some(2)
+        } else {
+            match self.spf[n >> 1] {
+                0 => Some(n),
+                p => Some(p as usize),
+            }
+        }
+    }
+
+    pub fn is_prime(&self, n: usize) -> Option<bool> {
+        if n >= self.limit {
+            None
+        } else {
+            Some(self.get(n) == Some(n))
+        }
+    }
+
+    /// Prime factorization of 0 < n < limit as (prime, exponent) pairs, by
+    /// repeatedly dividing out the smallest prime factor.
+    pub fn factorize(&self, n: usize) -> Option<Vec<(usize, u32)>> {
+        if n == 0 || n >= self.limit {
+            return None;
+        }
+        let mut n = n;
+        let mut result: Vec<(usize, u32)> = vec![];
+        while let Some(p) = self.get(n) {
+            match result.last_mut() {
+                Some((q, e)) if *q == p => *e += 1,
+                _ => result.push((p, 1)),
+            }
+            n /= p;
+        }
+        Some(result)
+    }
+}
+
+/// Euler's totient φ(n) for every n < limit.
+pub fn totients(limit: usize) -> Vec<u32> {
+    check_limit(limit);
+    let mut phi = vec![0u32; limit];
+    if limit > 1 {
+        phi[1] = 1;
+    }
+    linear_sieve(limit, |n, i, p| {
+        phi[n] = if i.is_multiple_of(p) {
+            phi[i] * p as u32
+        } else {
+            phi[i] * (p - 1) as u32
+        }
+    });
+    phi
+}
+
+/// The Möbius function μ(n) for every n < limit (μ(0) is 0).
+pub fn mobius(limit: usize) -> Vec<i8> {
+    check_limit(limit);
+    let mut mu = vec![0i8; limit];
+    if limit > 1 {
+        mu[1] = 1;
+    }
+    linear_sieve(limit, |n, i, p| {
+        mu[n] = if i.is_multiple_of(p) { 0 } else { -mu[i] }
+    });
+    mu
+}
+
+/// Number of divisors d(n) for every n < limit (d(0) is 0).
+pub fn divisor_counts(limit: usize) -> Vec<u32> {
+    check_limit(limit);
+    let mut d = vec![0u32; limit];
+    // exponent of the smallest prime factor of n
+    let mut e = vec![0u8; limit];
+    if limit > 1 {
+        d[1] = 1;
+    }
+    linear_sieve(limit, |n, i, p| {
+        if i.is_multiple_of(p) {
+            // p^k * m becomes p^(k+1) * m
+            let k = e[i] as u32;
+            d[n] = d[i] / (k + 1) * (k + 2);
+            e[n] = e[i] + 1;
+        } else {
+            d[n] = d[i] * 2;
+            e[n] = 1;
+        }
+    });
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sieve::{factor, PrimeBits};
+
+    const N: usize = 3000;
+
+    fn gcd(a: usize, b: usize) -> usize {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+
+    #[test]
+    fn test_spf() {
+        let spf = SmallestPrimeFactors::new(N);
+        let p = PrimeBits::new(N);
+        assert_eq!(spf.get(0), None);
+        assert_eq!(spf.get(1), None);
+        assert_eq!(spf.get(N), None);
+        for n in 2..N {
+            let smallest = (2..=n).find(|d| n.is_multiple_of(*d));
+            assert_eq!(spf.get(n), smallest);
+            assert_eq!(spf.is_prime(n), p.is_prime(n));
+            let expected: Vec<(usize, u32)> = factor::factorize(n as u64)
+                .into_iter()
+                .map(|(p, e)| (p as usize, e))
+                .collect();
+            assert_eq!(spf.factorize(n), Some(expected));
+        }
+        assert_eq!(spf.factorize(1), Some(vec![]));
+    }
+
+    #[test]
+    fn test_functions() {
+        let phi = totients(N);
+        let mu = mobius(N);
+        let d = divisor_counts(N);
+        for n in 1..N {
+            let coprime = (1..=n).filter(|k| gcd(n, *k) == 1).count();
+            assert_eq!(phi[n] as usize, coprime);
+            let divisors = (1..=n).filter(|k| n.is_multiple_of(*k)).count();
+            assert_eq!(d[n] as usize, divisors);
+            let f = factor::factorize(n as u64);
+            let expected = if f.iter().any(|(_, e)| *e > 1) {
+                0
+            } else if f.len().is_multiple_of(2) {
+                1
+            } else {
+                -1
+            };
+            assert_eq!(mu[n], expected);
+        }
+        assert!(totients(0).is_empty());
+        assert_eq!(mobius(2), vec![0, 1]);
+    }
+}
@@ -0,0 +1,265 @@
+// Segmented sieve of Eratosthenes for ranges far beyond what a PrimeBits table
+// can hold. Only the base primes up to sqrt(hi) are kept; the range itself is
+// sieved one cache-sized segment at a time, odd numbers only, so [lo, hi] can sit
+// anywhere up to ~10^12 without allocating a table for it.
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+};
+
+use crate::util::bit_vec::BitVec;
+
+use super::PrimeBits;
+
+// 32KiB of bits per segment, i.e. 2^18 odd numbers
+const SEGMENT_BITS: u64 = 1 << 18;
+
+pub struct SegmentedSieve {
+    lo: u64,
+    hi: u64,
+    // first odd number >= lo
+    first: u64,
+    segments: u64,
+    // odd primes up to sqrt(hi)
+    base: Vec<u64>,
+    threads: usize,
+}
+
+// largest r with r * r <= n
+fn isqrt(n: u64) -> u64 {
+    let mut r = (n as f64).sqrt() as u64;
+    while r * r > n {
+        r -= 1;
+    }
+    while (r + 1) * (r + 1) <= n {
+        r += 1;
+    }
+    r
+}
+
+impl SegmentedSieve {
+    /// Primes in the inclusive range [lo, hi].
+    pub fn new(lo: u64, hi: u64) -> Self {
+        let first = lo | 1;
+        let segments = if first > hi {
+            0
+        } else {
+            ((hi - first) / 2 + 1).div_ceil(SEGMENT_BITS)
+        };
+        let base = PrimeBits::new(isqrt(hi) as usize + 1)
+            .primes()
+            .skip(1)
+            .map(|p| p as u64)
+            .collect();
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            lo,
+            hi,
+            first,
+            segments,
+            base,
+            threads,
+        }
+    }
+
+    /// Number of worker threads used to sieve segments (defaults to the number of CPUs).
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0);
+        self.threads = threads;
+        self
+    }
+
+    fn has_two(&self) -> bool {
+        self.lo <= 2 && 2 <= self.hi
+    }
+
+    // sieve segment k: bit i of the result stands for the odd number start + 2i
+    fn sieve_segment(&self, k: u64) -> (u64, BitVec) {
+        let start = self.first + k * 2 * SEGMENT_BITS;
+        let nbits = SEGMENT_BITS.min((self.hi - start) / 2 + 1);
+        let last = start + 2 * (nbits - 1);
+
+        let mut bits = BitVec::ones(nbits as usize);
+        if start == 1 {
+            bits.clear(0); // 1 is not prime
+        }
+        for &p in self.base.iter() {
+            if p * p > last {
+                break;
+            }
+            // first odd multiple of p in the segment, but never p itself
+            let mut m = (start.div_ceil(p) * p).max(p * p);
+            if m & 1 == 0 {
+                m += p;
+            }
+            let mut j = (m - start) >> 1;
+            while j < nbits {
+                bits.clear(j as usize);
+                j += p;
+            }
+        }
+        (start, bits)
+    }
+
+    /// Number of primes in the range, sieving segments on all worker threads.
+    pub fn count(&self) -> u64 {
+        let next = AtomicU64::new(0);
+        let odd: u64 = thread::scope(|s| {
+            let workers: Vec<_> = (0..self.threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut n = 0;
+                        loop {
+                            let k = next.fetch_add(1, Ordering::Relaxed);
+                            if k >= self.segments {
+                                return n;
+                            }
+                            let (_, bits) = self.sieve_segment(k);
+                            n += bits.count_ones(0..bits.len()) as u64;
+                        }
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+        odd + self.has_two() as u64
+    }
+
+    /// Stream the primes in the range in ascending order. Segments are sieved a
+    /// batch at a time, one per worker thread, so only a handful are ever held.
+    pub fn primes(&self) -> Primes<'_> {
+        Primes {
+            sieve: self,
+            two: self.has_two(),
+            next_segment: 0,
+            ready: VecDeque::new(),
+            word: 0,
+            bits: 0,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a SegmentedSieve {
+    type Item = u64;
+    type IntoIter = Primes<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.primes()
+    }
+}
+
+pub struct Primes<'a> {
+    sieve: &'a SegmentedSieve,
+    two: bool,
+    next_segment: u64,
+    // sieved segments waiting to be read, the front one is being read now
+    ready: VecDeque<(u64, BitVec)>,
+    // position in the front segment and what's left of that word
+    word: usize,
+    bits: u64,
+}
+
+impl Primes<'_> {
+    fn refill(&mut self) {
+        let sieve = self.sieve;
+        let batch = (sieve.threads as u64).min(sieve.segments - self.next_segment);
+        let first = self.next_segment;
+        if batch == 1 {
+            self.ready.push_back(sieve.sieve_segment(first));
+        } else {
+            thread::scope(|s| {
+                let workers: Vec<_> = (first..first + batch)
+                    .map(|k| s.spawn(move || sieve.sieve_segment(k)))
+                    .collect();
+                for w in workers {
+                    self.ready.push_back(w.join().unwrap());
+                }
+            });
+        }
+        self.next_segment += batch;
+    }
+}
+
+impl Iterator for Primes<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.two {
+            self.two = false;
+            return Some(2);
+        }
+        loop {
+            if self.bits != 0 {
+                let bit = self.bits.trailing_zeros() as u64;
+                self.bits &= self.bits - 1;
+                let start = self.ready.front()?.0;
+                return Some(start + 2 * (((self.word as u64) << 6) + bit));
+            }
+            match self.ready.front() {
+                Some((_, bits)) if self.word + 1 < bits.words().len() => {
+                    self.word += 1;
+                    self.bits = bits.words()[self.word];
+                }
+                _ => {
+                    // move on to the next segment
+                    self.ready.pop_front();
+                    if self.ready.is_empty() {
+                        if self.next_segment >= self.sieve.segments {
+                            return None;
+                        }
+                        self.refill();
+                    }
+                    self.word = 0;
+                    self.bits = self.ready.front()?.1.words()[0];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let p = PrimeBits::new(2_000_000);
+        for (lo, hi) in [
+            (0, 0),
+            (0, 1),
+            (0, 2),
+            (2, 3),
+            (3, 3),
+            (4, 4),
+            (0, 100),
+            (90, 1_999_999),
+        ] {
+            let expected: Vec<u64> = p
+                .primes()
+                .map(|n| n as u64)
+                .filter(|n| lo <= *n && *n <= hi)
+                .collect();
+            let s = SegmentedSieve::new(lo, hi).with_threads(3);
+            assert_eq!(s.primes().collect::<Vec<_>>(), expected);
+            assert_eq!(s.count(), expected.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_count() {
+        assert_eq!(SegmentedSieve::new(0, 10_000_000).count(), 664_579);
+        assert_eq!(
+            SegmentedSieve::new(0, 10_000_000).with_threads(1).count(),
+            664_579
+        );
+    }
+
+    #[test]
+    fn test_large() {
+        let lo = 1_000_000_000_000;
+        let s = SegmentedSieve::new(lo, lo + 200);
+        let primes: Vec<u64> = s.primes().map(|p| p - lo).collect();
+        assert_eq!(primes, vec![39, 61, 63, 91, 121, 163, 169, 177, 189, 193]);
+    }
+}
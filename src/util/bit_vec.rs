@@ -0,0 +1,354 @@
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Range};
+
+// rank directory granularity: one cumulative count per 8 words (512 bits)
+const BLOCK_WORDS: usize = 8;
+
+/// A fixed length vector of bits packed into u64 words, bit i of the vector
+/// being bit (i & 63) of word (i >> 6).
+///
+/// `rank1` and `select1` work at any time, but call `build_index` once the bits
+/// stop changing to make them O(1) and O(log n). Any mutation drops the index.
+#[derive(Debug, Clone)]
+pub struct BitVec {
+    words: Vec<u64>,
+    len: usize,
+    // blocks[b] = number of ones before word b * BLOCK_WORDS
+    blocks: Vec<usize>,
+}
+
+impl PartialEq for BitVec {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.words == other.words
+    }
+}
+
+impl Eq for BitVec {}
+
+impl BitVec {
+    /// Division free (quotient, remainder) of n by 64.
+    pub fn bit_div_mod64(n: u64) -> (u64, u64) {
+        let q = n >> 6;
+        let r = n & 0x3F;
+        (q, r)
+    }
+
+    // len bits, all zero
+    pub fn new(len: usize) -> Self {
+        let (words, rem) = Self::bit_div_mod64(len as u64);
+        let words = words as usize + (rem != 0) as usize;
+        Self {
+            words: vec![0; words],
+            len,
+            blocks: vec![],
+        }
+    }
+
+    // len bits, all one
+    pub fn ones(len: usize) -> Self {
+        let (words, rem) = Self::bit_div_mod64(len as u64);
+        let mut v = vec![0xFFFF_FFFF_FFFF_FFFFu64; words as usize];
+        if rem != 0 {
+            // keep the padding past len clear so whole word counts stay right
+            v.push((1 << rem) - 1);
+        }
+        Self {
+            words: v,
+            len,
+            blocks: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // the underlying words; bits past len are always zero
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn set(&mut self, i: usize) {
+        assert!(i < self.len);
+        self.blocks.clear();
+        let (elem, bit) = Self::bit_div_mod64(i as u64);
+        self.words[elem as usize] |= 1 << bit;
+    }
+
+    pub fn clear(&mut self, i: usize) {
+        assert!(i < self.len);
+        self.blocks.clear();
+        let (elem, bit) = Self::bit_div_mod64(i as u64);
+        self.words[elem as usize] &= !(1 << bit);
+    }
+
+    pub fn is_set(&self, i: usize) -> bool {
+        let (elem, bit) = Self::bit_div_mod64(i as u64);
+        let mask: u64 = 1 << bit;
+        self.words[elem as usize] & mask == mask
+    }
+
+    /// extracts n bits starting at bit m into a u64
+    // n <= 64 and n > 0 and m + n <= len
+    pub fn extract(&self, m: u64, n: u64) -> u64 {
+        debug_assert!(n > 0 && n <= 64 && (m + n) as usize <= self.len);
+        let (elem1, bit1) = Self::bit_div_mod64(m);
+        let (elem2, bit2) = Self::bit_div_mod64(m + n);
+        if n == 64 && bit1 == 0 {
+            return self.words[elem1 as usize];
+        }
+        if bit2 == 0 {
+            return self.words[elem1 as usize] >> (64 - n);
+        }
+        if elem1 == elem2 {
+            // section from a single element
+            let mut result = self.words[elem1 as usize];
+            result <<= 64 - bit2;
+            return result >> (64 - n);
+        }
+        // section split across two element
+        let mut back = self.words[elem1 as usize];
+        back >>= bit1;
+        let mut result = self.words[elem2 as usize];
+        result <<= 64 - bit2;
+        result >>= 64 - n;
+        result | back
+    }
+
+    /// writes the low n bits of value at bit m, the inverse of extract
+    // n <= 64 and n > 0 and m + n <= len
+    pub fn insert_bits(&mut self, m: u64, n: u64, value: u64) {
+        assert!(n > 0 && n <= 64 && (m + n) as usize <= self.len);
+        self.blocks.clear();
+        let mask = if n == 64 { u64::MAX } else { (1 << n) - 1 };
+        let value = value & mask;
+        let (elem1, bit1) = Self::bit_div_mod64(m);
+        let elem1 = elem1 as usize;
+        self.words[elem1] &= !(mask << bit1);
+        self.words[elem1] |= value << bit1;
+        if bit1 + n > 64 {
+            // the rest spills into the next element
+            let spill = 64 - bit1;
+            self.words[elem1 + 1] &= !(mask >> spill);
+            self.words[elem1 + 1] |= value >> spill;
+        }
+    }
+
+    /// number of set bits in the range
+    pub fn count_ones(&self, range: Range<usize>) -> usize {
+        assert!(range.start <= range.end && range.end <= self.len);
+        if range.is_empty() {
+            return 0;
+        }
+        let (elem1, bit1) = Self::bit_div_mod64(range.start as u64);
+        let (elem2, bit2) = Self::bit_div_mod64(range.end as u64);
+        let (elem1, elem2) = (elem1 as usize, elem2 as usize);
+        let low = self.words[elem1] & (u64::MAX << bit1);
+        if elem1 == elem2 {
+            return (low & ((1 << bit2) - 1)).count_ones() as usize;
+        }
+        let mut num = low.count_ones() as usize;
+        for w in &self.words[elem1 + 1..elem2] {
+            num += w.count_ones() as usize;
+        }
+        if bit2 != 0 {
+            num += (self.words[elem2] & ((1 << bit2) - 1)).count_ones() as usize;
+        }
+        num
+    }
+
+    /// Build the rank directory used by rank1 and select1.
+    pub fn build_index(&mut self) {
+        let mut blocks = Vec::with_capacity(self.words.len() / BLOCK_WORDS + 1);
+        let mut num = 0;
+        for chunk in self.words.chunks(BLOCK_WORDS) {
+            blocks.push(num);
+            num += chunk.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+        }
+        blocks.push(num);
+        self.blocks = blocks;
+    }
+
+    /// number of set bits before bit i
+    pub fn rank1(&self, i: usize) -> usize {
+        assert!(i <= self.len);
+        if self.blocks.is_empty() {
+            return self.count_ones(0..i);
+        }
+        let (elem, bit) = Self::bit_div_mod64(i as u64);
+        let elem = elem as usize;
+        let block = elem / BLOCK_WORDS;
+        let mut num = self.blocks[block];
+        for w in &self.words[block * BLOCK_WORDS..elem] {
+            num += w.count_ones() as usize;
+        }
+        if bit != 0 {
+            num += (self.words[elem] & ((1 << bit) - 1)).count_ones() as usize;
+        }
+        num
+    }
+
+    /// position of the k-th set bit (counting from 0), or None if there are not that many
+    pub fn select1(&self, k: usize) -> Option<usize> {
+        let (mut elem, mut seen) = if self.blocks.is_empty() {
+            (0, 0)
+        } else {
+            if k >= self.blocks[self.blocks.len() - 1] {
+                return None;
+            }
+            // last block that starts with at most k ones before it
+            let block = self.blocks.partition_point(|n| *n <= k) - 1;
+            (block * BLOCK_WORDS, self.blocks[block])
+        };
+        while elem < self.words.len() {
+            let ones = self.words[elem].count_ones() as usize;
+            if seen + ones > k {
+                // drop the lower set bits until the one we want is lowest
+                let mut w = self.words[elem];
+                for _ in 0..k - seen {
+                    w &= w - 1;
+                }
+                return Some((elem << 6) + w.trailing_zeros() as usize);
+            }
+            seen += ones;
+            elem += 1;
+        }
+        None
+    }
+
+    /// positions of the set bits, in order
+    pub fn iter_ones(&self) -> Ones<'_> {
+        Ones {
+            words: &self.words,
+            elem: 0,
+            bits: self.words.first().copied().unwrap_or(0),
+        }
+    }
+}
+
+pub struct Ones<'a> {
+    words: &'a [u64],
+    elem: usize,
+    bits: u64,
+}
+
+impl Iterator for Ones<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.bits == 0 {
+            self.elem += 1;
+            self.bits = *self.words.get(self.elem)?;
+        }
+        let bit = self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        Some((self.elem << 6) + bit)
+    }
+}
+
+macro_rules! bit_op {
+    ($op:ident, $f:ident, $op_assign:ident, $f_assign:ident) => {
+        impl $op_assign<&BitVec> for BitVec {
+            fn $f_assign(&mut self, other: &BitVec) {
+                assert_eq!(self.len, other.len);
+                self.blocks.clear();
+                for (a, b) in self.words.iter_mut().zip(other.words.iter()) {
+                    a.$f_assign(*b);
+                }
+            }
+        }
+
+        impl $op<&BitVec> for &BitVec {
+            type Output = BitVec;
+
+            fn $f(self, other: &BitVec) -> BitVec {
+                let mut v = self.clone();
+                v.$f_assign(other);
+                v
+            }
+        }
+    };
+}
+
+bit_op!(BitAnd, bitand, BitAndAssign, bitand_assign);
+bit_op!(BitOr, bitor, BitOrAssign, bitor_assign);
+bit_op!(BitXor, bitxor, BitXorAssign, bitxor_assign);
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn random(len: usize, rng: &mut StdRng) -> BitVec {
+        let mut v = BitVec::new(len);
+        for i in 0..len {
+            if rng.gen_bool(0.3) {
+                v.set(i);
+            }
+        }
+        v
+    }
+
+    #[test]
+    fn test_extract_insert() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let v = random(300, &mut rng);
+        let mut w = BitVec::new(300);
+        for m in 0..236 {
+            for n in 1..=64 {
+                let bits = v.extract(m, n);
+                for i in 0..n {
+                    assert_eq!(bits >> i & 1 == 1, v.is_set((m + i) as usize));
+                }
+                w.insert_bits(m, n, bits);
+                assert_eq!(w.extract(m, n), bits);
+            }
+        }
+        assert_eq!(v, w);
+        assert_eq!(v.extract(64, 64), v.words()[1]);
+    }
+
+    #[test]
+    fn test_rank_select() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for len in [0, 1, 63, 64, 65, 511, 512, 513, 5000] {
+            let mut v = random(len, &mut rng);
+            let ones: Vec<usize> = (0..len).filter(|i| v.is_set(*i)).collect();
+            assert_eq!(v.iter_ones().collect::<Vec<_>>(), ones);
+            for indexed in [false, true] {
+                if indexed {
+                    v.build_index();
+                }
+                for i in 0..=len {
+                    assert_eq!(v.rank1(i), ones.iter().filter(|o| **o < i).count());
+                }
+                for (k, o) in ones.iter().enumerate() {
+                    assert_eq!(v.select1(k), Some(*o));
+                }
+                assert_eq!(v.select1(ones.len()), None);
+            }
+            assert_eq!(v.count_ones(0..len), ones.len());
+        }
+    }
+
+    #[test]
+    fn test_ops() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let a = random(200, &mut rng);
+        let b = random(200, &mut rng);
+        let and = &a & &b;
+        let or = &a | &b;
+        let xor = &a ^ &b;
+        for i in 0..200 {
+            assert_eq!(and.is_set(i), a.is_set(i) && b.is_set(i));
+            assert_eq!(or.is_set(i), a.is_set(i) || b.is_set(i));
+            assert_eq!(xor.is_set(i), a.is_set(i) != b.is_set(i));
+        }
+        assert_eq!(BitVec::ones(130).count_ones(0..130), 130);
+        assert_eq!(BitVec::ones(130).count_ones(3..67), 64);
+    }
+}
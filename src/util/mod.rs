@@ -1,5 +1,7 @@
 pub mod bfs_directed;
+pub mod bit_vec;
 pub mod digraph;
 pub mod point2d;
 pub mod rect_hv;
+pub mod region;
 pub mod std_draw;
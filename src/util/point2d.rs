@@ -1,15 +1,17 @@
+use std::cmp::Ordering;
+
 use float_ord::FloatOrd;
 
 use super::std_draw::Plot;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Point2D {
     x: FloatOrd<f64>,
     y: FloatOrd<f64>,
 }
 
 impl Ord for Point2D {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    fn cmp(&self, other: &Self) -> Ordering {
         if self.y == other.y {
             self.x.cmp(&other.x)
         } else {
@@ -19,7 +21,7 @@ impl Ord for Point2D {
 }
 
 impl PartialOrd for Point2D {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
@@ -55,6 +57,55 @@ impl Point2D {
         dx * dx + dy * dy
     }
 
+    // angle of the line from this point to that one, in radians in (-pi, pi]
+    pub fn angle_to(&self, that: &Point2D) -> f64 {
+        (that.y() - self.y()).atan2(that.x() - self.x())
+    }
+
+    // twice the signed area of the triangle a-b-c: positive if a->b->c turns
+    // counterclockwise, negative if clockwise, 0 if they are collinear
+    pub fn area2(a: &Point2D, b: &Point2D, c: &Point2D) -> f64 {
+        (b.x() - a.x()) * (c.y() - a.y()) - (b.y() - a.y()) * (c.x() - a.x())
+    }
+
+    // is a->b->c a counterclockwise turn? +1 if so, -1 if clockwise, 0 if collinear
+    pub fn ccw(a: &Point2D, b: &Point2D, c: &Point2D) -> i32 {
+        let area2 = Self::area2(a, b, c);
+        if area2 < 0.0 {
+            -1
+        } else if area2 > 0.0 {
+            1
+        } else {
+            0
+        }
+    }
+
+    // compares two points by polar angle (between 0 and 2pi) with respect to this
+    // point, with ccw rather than trigonometry so it's exact. Points in the same
+    // direction compare equal.
+    pub fn polar_order(&self) -> impl Fn(&Point2D, &Point2D) -> Ordering + '_ {
+        move |q1, q2| {
+            let (dx1, dy1) = (q1.x() - self.x(), q1.y() - self.y());
+            let (dx2, dy2) = (q2.x() - self.x(), q2.y() - self.y());
+            if dy1 >= 0.0 && dy2 < 0.0 {
+                // q1 above, q2 below
+                Ordering::Less
+            } else if dy2 >= 0.0 && dy1 < 0.0 {
+                Ordering::Greater
+            } else if dy1 == 0.0 && dy2 == 0.0 {
+                // both on the horizontal line, to the right comes first
+                (dx2 >= 0.0).cmp(&(dx1 >= 0.0))
+            } else {
+                0.cmp(&Self::ccw(self, q1, q2))
+            }
+        }
+    }
+
+    // compares two points by atan2 angle (between -pi and pi) with respect to this point
+    pub fn atan2_order(&self) -> impl Fn(&Point2D, &Point2D) -> Ordering + '_ {
+        move |q1, q2| self.angle_to(q1).total_cmp(&self.angle_to(q2))
+    }
+
     // draw to standard draw
     pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
         plot.point(self.x.0, self.y.0)?;
@@ -64,4 +115,53 @@ impl Point2D {
         plot.point_match(self.x.0, self.y.0)?;
         Ok(())
     }
+
+    // draw the line from this point to that one to standard draw
+    pub fn draw_to(&self, that: &Point2D, plot: &mut Plot) -> anyhow::Result<()> {
+        plot.line(self.x(), self.y(), that.x(), that.y())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ccw() {
+        let (a, b) = (Point2D::new(0.0, 0.0), Point2D::new(1.0, 0.0));
+        assert_eq!(Point2D::ccw(&a, &b, &Point2D::new(1.0, 1.0)), 1);
+        assert_eq!(Point2D::ccw(&a, &b, &Point2D::new(1.0, -1.0)), -1);
+        assert_eq!(Point2D::ccw(&a, &b, &Point2D::new(5.0, 0.0)), 0);
+        assert_eq!(Point2D::area2(&a, &b, &Point2D::new(3.0, 2.0)), 2.0);
+        assert_eq!(
+            a.angle_to(&Point2D::new(0.0, 2.0)),
+            std::f64::consts::FRAC_PI_2
+        );
+    }
+
+    #[test]
+    fn test_orders() {
+        let o = Point2D::new(0.5, 0.5);
+        // every 45 degrees from the positive x axis, twice over at different distances
+        let mut points = vec![];
+        for r in [1.0, 2.0] {
+            for i in 0..8 {
+                let a = std::f64::consts::FRAC_PI_4 * i as f64;
+                let (x, y) = ((r * a.cos()).round(), (r * a.sin()).round());
+                points.push(Point2D::new(0.5 + x, 0.5 + y));
+            }
+        }
+        let mut polar = points.clone();
+        polar.sort_by(o.polar_order());
+        // stable, so equal angles keep their order
+        for (i, p) in polar.iter().enumerate() {
+            assert_eq!(*p, points[i / 2 + (i % 2) * 8]);
+        }
+        // the same, except that atan2 starts at -pi
+        let mut atan2 = points.clone();
+        atan2.sort_by(o.atan2_order());
+        polar.rotate_left(10);
+        assert_eq!(atan2[1..], polar[1..]);
+        assert_eq!(o.polar_order()(&points[0], &points[8]), Ordering::Equal);
+    }
 }
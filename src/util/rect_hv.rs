@@ -1,6 +1,6 @@
 use super::{point2d::Point2D, std_draw::Plot};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct RectHV {
     xmin: f64,
     ymin: f64,
@@ -71,6 +71,46 @@ impl RectHV {
         dx * dx + dy * dy
     }
 
+    pub fn width(&self) -> f64 {
+        self.xmax - self.xmin
+    }
+
+    pub fn height(&self) -> f64 {
+        self.ymax - self.ymin
+    }
+
+    pub fn area(&self) -> f64 {
+        self.width() * self.height()
+    }
+
+    // the point in the middle of the rectangle
+    pub fn center(&self) -> Point2D {
+        Point2D::new((self.xmin + self.xmax) / 2.0, (self.ymin + self.ymax) / 2.0)
+    }
+
+    // does this rectangle contain all of that rectangle (boundaries included)?
+    pub fn contains_rect(&self, other: &RectHV) -> bool {
+        self.xmin <= other.xmin
+            && other.xmax <= self.xmax
+            && self.ymin <= other.ymin
+            && other.ymax <= self.ymax
+    }
+
+    // the smallest rectangle containing both rectangles
+    pub fn union(&self, other: &RectHV) -> RectHV {
+        RectHV::new(
+            self.xmin.min(other.xmin),
+            self.ymin.min(other.ymin),
+            self.xmax.max(other.xmax),
+            self.ymax.max(other.ymax),
+        )
+    }
+
+    // how much area union would add to this rectangle
+    pub fn enlargement(&self, other: &RectHV) -> f64 {
+        self.union(other).area() - self.area()
+    }
+
     // draw to standard draw
     pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
         plot.line(self.xmin, self.ymin, self.xmax, self.ymin)?;
@@ -80,3 +120,22 @@ impl RectHV {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union() {
+        let a = RectHV::new(0.0, 0.0, 1.0, 2.0);
+        let b = RectHV::new(2.0, 1.0, 3.0, 3.0);
+        assert_eq!(a.area(), 2.0);
+        assert_eq!(a.center(), Point2D::new(0.5, 1.0));
+        let u = a.union(&b);
+        assert_eq!(u, RectHV::new(0.0, 0.0, 3.0, 3.0));
+        assert!(u.contains_rect(&a) && u.contains_rect(&b) && u.contains_rect(&u));
+        assert!(!a.contains_rect(&u));
+        assert_eq!(a.enlargement(&b), 7.0);
+        assert_eq!(u.enlargement(&a), 0.0);
+    }
+}
@@ -0,0 +1,193 @@
+use super::{point2d::Point2D, rect_hv::RectHV, std_draw::Plot};
+
+/// A query shape for range searches. Spatial indexes only need to know whether
+/// a point is inside the shape, and whether the shape reaches into the bounding
+/// rectangle of a subtree (if not, the whole subtree can be skipped).
+pub trait Region {
+    // does the region contain the point p (either inside or on boundary)?
+    fn contains(&self, p: &Point2D) -> bool;
+
+    // might the region contain some point of the rectangle? May answer true
+    // when it doesn't, that only costs pruning, but must not answer false when it does.
+    fn intersects(&self, rect: &RectHV) -> bool;
+}
+
+impl<R: Region + ?Sized> Region for &R {
+    fn contains(&self, p: &Point2D) -> bool {
+        (**self).contains(p)
+    }
+
+    fn intersects(&self, rect: &RectHV) -> bool {
+        (**self).intersects(rect)
+    }
+}
+
+impl Region for RectHV {
+    fn contains(&self, p: &Point2D) -> bool {
+        RectHV::contains(self, p)
+    }
+
+    fn intersects(&self, rect: &RectHV) -> bool {
+        RectHV::intersects(self, rect)
+    }
+}
+
+/// All points within `radius` of `center`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Circle {
+    center: Point2D,
+    radius: f64,
+}
+
+impl Circle {
+    pub fn new(center: Point2D, radius: f64) -> Self {
+        assert!(radius >= 0.0);
+        Self { center, radius }
+    }
+
+    pub fn center(&self) -> &Point2D {
+        &self.center
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    // draw to standard draw, as a polygon with enough sides to look round
+    pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        const SIDES: usize = 64;
+        let (cx, cy, r) = (self.center.x(), self.center.y(), self.radius);
+        for i in 0..SIDES {
+            let a0 = std::f64::consts::TAU * i as f64 / SIDES as f64;
+            let a1 = std::f64::consts::TAU * (i + 1) as f64 / SIDES as f64;
+            plot.line(
+                cx + r * a0.cos(),
+                cy + r * a0.sin(),
+                cx + r * a1.cos(),
+                cy + r * a1.sin(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Region for Circle {
+    fn contains(&self, p: &Point2D) -> bool {
+        self.center.distance_squared_to(p) <= self.radius * self.radius
+    }
+
+    fn intersects(&self, rect: &RectHV) -> bool {
+        rect.distance_squared_to(&self.center) <= self.radius * self.radius
+    }
+}
+
+/// A convex polygon, including its boundary.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConvexPolygon {
+    // counterclockwise
+    vertices: Vec<Point2D>,
+    bounds: RectHV,
+}
+
+impl ConvexPolygon {
+    // the vertices in order around the polygon, either direction
+    pub fn new(mut vertices: Vec<Point2D>) -> Self {
+        assert!(vertices.len() >= 3);
+        let n = vertices.len();
+        let twice_area: f64 = (0..n)
+            .map(|i| {
+                let (a, b) = (&vertices[i], &vertices[(i + 1) % n]);
+                a.x() * b.y() - b.x() * a.y()
+            })
+            .sum();
+        if twice_area < 0.0 {
+            vertices.reverse();
+        }
+        let (mut xmin, mut ymin) = (f64::INFINITY, f64::INFINITY);
+        let (mut xmax, mut ymax) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for v in vertices.iter() {
+            xmin = xmin.min(v.x());
+            ymin = ymin.min(v.y());
+            xmax = xmax.max(v.x());
+            ymax = ymax.max(v.y());
+        }
+        let bounds = RectHV::new(xmin, ymin, xmax, ymax);
+        Self { vertices, bounds }
+    }
+
+    pub fn vertices(&self) -> &[Point2D] {
+        &self.vertices
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (&Point2D, &Point2D)> {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+    }
+
+    // draw to standard draw
+    pub fn draw(&self, plot: &mut Plot) -> anyhow::Result<()> {
+        for (a, b) in self.edges() {
+            plot.line(a.x(), a.y(), b.x(), b.y())?;
+        }
+        Ok(())
+    }
+}
+
+impl Region for ConvexPolygon {
+    fn contains(&self, p: &Point2D) -> bool {
+        // counterclockwise, so the inside is to the left of every edge
+        self.edges().all(|(a, b)| Point2D::area2(a, b, p) >= 0.0)
+    }
+
+    fn intersects(&self, rect: &RectHV) -> bool {
+        /* Separating axis theorem: two convex shapes are disjoint exactly when a line
+        parallel to one of their edges separates them. For the rectangle's edges that's
+        the bounding box test, for the polygon's edges it means all four corners of the
+        rectangle lie to the right of the edge. */
+        if !self.bounds.intersects(rect) {
+            return false;
+        }
+        let corners = [
+            Point2D::new(rect.xmin(), rect.ymin()),
+            Point2D::new(rect.xmax(), rect.ymin()),
+            Point2D::new(rect.xmax(), rect.ymax()),
+            Point2D::new(rect.xmin(), rect.ymax()),
+        ];
+        self.edges()
+            .all(|(a, b)| corners.iter().any(|c| Point2D::area2(a, b, c) >= 0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circle() {
+        let c = Circle::new(Point2D::new(0.5, 0.5), 0.25);
+        assert!(c.contains(&Point2D::new(0.5, 0.75)));
+        assert!(!c.contains(&Point2D::new(0.7, 0.7)));
+        assert!(c.intersects(&RectHV::new(0.7, 0.4, 0.8, 0.6)));
+        assert!(!c.intersects(&RectHV::new(0.7, 0.7, 0.8, 0.8)));
+    }
+
+    #[test]
+    fn test_polygon() {
+        // a diamond, given clockwise
+        let d = ConvexPolygon::new(vec![
+            Point2D::new(0.5, 1.0),
+            Point2D::new(1.0, 0.5),
+            Point2D::new(0.5, 0.0),
+            Point2D::new(0.0, 0.5),
+        ]);
+        assert!(d.contains(&Point2D::new(0.5, 0.5)));
+        assert!(d.contains(&Point2D::new(0.75, 0.75)));
+        assert!(!d.contains(&Point2D::new(0.8, 0.8)));
+        assert!(d.intersects(&RectHV::new(0.4, 0.4, 0.6, 0.6)));
+        assert!(d.intersects(&RectHV::new(0.0, 0.0, 1.0, 1.0)));
+        // inside the bounding box but past the diagonal edge
+        assert!(!d.intersects(&RectHV::new(0.8, 0.8, 1.0, 1.0)));
+        assert!(!d.intersects(&RectHV::new(2.0, 2.0, 3.0, 3.0)));
+    }
+}
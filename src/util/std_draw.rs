@@ -9,6 +9,7 @@ pub struct Plot<'a> {
     ymax: f64,
     width: usize,
     height: usize,
+    pen_color: RGBColor,
     backend: DrawingArea<BitMapBackend<'a, RGBPixel>, Shift>,
 }
 
@@ -24,6 +25,7 @@ impl Plot<'_> {
             ymax: DEFAULT_SIZE as f64,
             width: DEFAULT_SIZE,
             height: DEFAULT_SIZE,
+            pen_color: BLUE,
             backend,
         })
     }
@@ -38,6 +40,11 @@ impl Plot<'_> {
         self.ymax = max;
     }
 
+    // color of the lines drawn from now on (blue to begin with)
+    pub fn set_pen_color(&mut self, color: RGBColor) {
+        self.pen_color = color;
+    }
+
     pub fn scale_x(&mut self, x: f64) -> f64 {
         self.width as f64 * (x - self.xmin) / (self.xmax - self.xmin)
     }
@@ -72,8 +79,10 @@ impl Plot<'_> {
         let xs1 = self.scale_x(x1) as i32;
         let ys1 = self.scale_y(y1) as i32;
 
-        self.backend
-            .draw(&PathElement::new(vec![(xs0, ys0), (xs1, ys1)], BLUE))?;
+        self.backend.draw(&PathElement::new(
+            vec![(xs0, ys0), (xs1, ys1)],
+            self.pen_color,
+        ))?;
         Ok(())
     }
 